@@ -0,0 +1,55 @@
+//! Compares describing every client window one round trip at a time with
+//! `Connection::windows`, which sends all requests before waiting for any reply.
+//!
+//! Needs a running X server with an EWMH window manager, run it with `cargo bench`.
+
+#[cfg(target_os = "linux")]
+fn main() {
+	use std::time::{Duration, Instant};
+
+	use window_titles::{Backend, Connection, ConnectionTrait};
+	use xcb::x;
+
+	const ITERATIONS: u32 = 50;
+
+	if std::env::var_os("DISPLAY").is_none() {
+		println!("DISPLAY is not set, skipping");
+		return;
+	}
+
+	let connection = Connection::with_backend(Backend::X11).expect("connecting to the X server");
+	let (serial, _) = xcb::Connection::connect(None).expect("connecting to the X server");
+	let atom = |name: &str| {
+		let cookie = serial.send_request(&x::InternAtom { only_if_exists: false, name: name.as_bytes() });
+		serial.wait_for_reply(cookie).unwrap().atom()
+	};
+	let (client_list, string, window_name, window_pid) = (atom("_NET_CLIENT_LIST"), atom("UTF8_STRING"), atom("_NET_WM_NAME"), atom("_NET_WM_PID"));
+	let property = |window, property, r#type| {
+		let cookie = serial.send_request(&x::GetProperty { delete: false, window, property, r#type, long_offset: 0, long_length: 1024 });
+		serial.wait_for_reply(cookie)
+	};
+
+	let time = |name: &str, run: &dyn Fn() -> usize| {
+		let start = Instant::now();
+		let windows: usize = (0..ITERATIONS).map(|_| run()).sum();
+		let elapsed: Duration = start.elapsed() / ITERATIONS;
+		println!("{:<10} {:>5} windows {:>12?} per query", name, windows / ITERATIONS as usize, elapsed);
+	};
+
+	time("serial", &|| {
+		let root = serial.get_setup().roots().next().unwrap().root();
+		let clients = property(root, client_list, x::ATOM_WINDOW).unwrap();
+		clients.value::<x::Window>().iter().filter(|&&window| {
+			let attributes = serial.send_request(&x::GetWindowAttributes { window });
+			serial.wait_for_reply(attributes).is_ok()
+				& property(window, window_name, string).is_ok()
+				& property(window, x::ATOM_WM_NAME, x::ATOM_ANY).is_ok()
+				& property(window, window_pid, x::ATOM_CARDINAL).is_ok()
+				& property(window, x::ATOM_WM_CLASS, x::ATOM_STRING).is_ok()
+		}).count()
+	});
+	time("pipelined", &|| connection.windows().unwrap().len());
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {}
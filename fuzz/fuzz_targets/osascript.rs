@@ -0,0 +1,17 @@
+//! Feeds arbitrary output to the `osascript -ss` parser, which must return an error rather than panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../src/applescript.rs"]
+mod applescript;
+
+fuzz_target!(|data: &[u8]| {
+	if let Ok(source) = std::str::from_utf8(data) {
+		if let Err(error) = applescript::parse(source) {
+			assert!(source.is_char_boundary(error.offset));
+		}
+	}
+});
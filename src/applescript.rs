@@ -0,0 +1,311 @@
+//! The `osascript -ss` output grammar: lists and records of quoted strings and bare literals.
+//!
+//! Kept free of the rest of the crate so the fuzz target can include it on its own.
+
+use std::fmt;
+
+/// Deeper nesting is rejected rather than risking the stack, `osascript` output for windows
+/// is three levels deep.
+const MAX_DEPTH: usize = 64;
+
+/// A value in the `osascript -ss` output.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+	List(Vec<Value>),
+	/// A record with its labels, in order.
+	Record(Vec<(String, Value)>),
+	String(String),
+	/// Any other value verbatim, such as numbers, `true`, `missing value` or `date "…"`.
+	Literal(String),
+}
+
+impl Value {
+	pub(crate) fn list(&self) -> &[Value] {
+		match self {
+			Value::List(values) => values,
+			_ => &[],
+		}
+	}
+}
+
+/// Where and why the output could not be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ParseError {
+	/// Byte offset into the output.
+	pub(crate) offset: usize,
+	pub(crate) message: &'static str,
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		write!(fmt, "{} at byte {}", self.message, self.offset)
+	}
+}
+
+/// Parses the output of an `osascript -ss` run, which holds a single value.
+pub(crate) fn parse(source: &str) -> Result<Value, ParseError> {
+	let mut parser = Parser { source, offset: 0 };
+	parser.skip_whitespace();
+	if parser.peek().is_none() {
+		return parser.error("empty output");
+	}
+	let start = parser.offset;
+	let value = match parser.item(0)? {
+		(None, value) => value,
+		(Some(_), _) => return Err(ParseError { offset: start, message: "label outside of a record" }),
+	};
+	parser.skip_whitespace();
+	match parser.bump() {
+		None => Ok(value),
+		Some(_) => parser.error_before("expected the end of the output"),
+	}
+}
+
+struct Parser<'a> {
+	source: &'a str,
+	offset: usize,
+}
+
+impl Parser<'_> {
+	/// A value, or a labeled one as found in records.
+	fn item(&mut self, depth: usize) -> Result<(Option<String>, Value), ParseError> {
+		self.skip_whitespace();
+		if matches!(self.peek(), Some('{' | '"')) {
+			return Ok((None, self.value(depth)?));
+		}
+		let literal = self.literal()?;
+		self.skip_whitespace();
+		if self.peek() != Some(':') {
+			return Ok((None, Value::Literal(literal)));
+		}
+		self.bump();
+		Ok((Some(literal), self.value(depth)?))
+	}
+
+	fn value(&mut self, depth: usize) -> Result<Value, ParseError> {
+		self.skip_whitespace();
+		match self.peek() {
+			Some('{') => self.container(depth + 1),
+			Some('"') => Ok(Value::String(self.string()?)),
+			_ => Ok(Value::Literal(self.literal()?)),
+		}
+	}
+
+	/// A list or record, the opening brace still ahead.
+	fn container(&mut self, depth: usize) -> Result<Value, ParseError> {
+		if depth > MAX_DEPTH {
+			return self.error("nesting too deep");
+		}
+		let start = self.offset;
+		self.bump();
+		self.skip_whitespace();
+		if self.peek() == Some('}') {
+			self.bump();
+			return Ok(Value::List(Vec::new()));
+		}
+		let mut values = Vec::new();
+		let mut entries = Vec::new();
+		loop {
+			self.skip_whitespace();
+			let item = self.offset;
+			match self.item(depth)? {
+				(None, value) if entries.is_empty() => values.push(value),
+				(Some(label), value) if values.is_empty() => entries.push((label, value)),
+				_ => return Err(ParseError { offset: item, message: "record mixes labeled and unlabeled items" }),
+			}
+			self.skip_whitespace();
+			match self.bump() {
+				Some(',') => {},
+				Some('}') => break,
+				None => return Err(ParseError { offset: start, message: "unclosed `{`" }),
+				Some(_) => return self.error_before("expected `,` or `}`"),
+			}
+		}
+		match entries.is_empty() {
+			true => Ok(Value::List(values)),
+			false => Ok(Value::Record(entries)),
+		}
+	}
+
+	/// A quoted string, the opening quote still ahead. `-ss` escapes quotes, backslashes,
+	/// tabs, line feeds and carriage returns, other escapes are kept as they are.
+	fn string(&mut self) -> Result<String, ParseError> {
+		let start = self.offset;
+		self.bump();
+		let mut string = String::new();
+		loop {
+			match self.bump() {
+				None => return Err(ParseError { offset: start, message: "unterminated string" }),
+				Some('"') => return Ok(string),
+				Some('\\') => match self.bump() {
+					None => return Err(ParseError { offset: start, message: "unterminated string" }),
+					Some('n') => string.push('\n'),
+					Some('t') => string.push('\t'),
+					Some('r') => string.push('\r'),
+					Some(c @ ('"' | '\\')) => string.push(c),
+					Some(c) => {
+						string.push('\\');
+						string.push(c);
+					},
+				},
+				Some(c) => string.push(c),
+			}
+		}
+	}
+
+	/// Bare words up to the next separator, including any quoted strings and `|…|`
+	/// identifiers among them.
+	fn literal(&mut self) -> Result<String, ParseError> {
+		let start = self.offset;
+		loop {
+			match self.peek() {
+				None | Some(',' | '{' | '}' | ':') => break,
+				Some('"') => {
+					self.string()?;
+				},
+				Some('|') => {
+					let pipe = self.offset;
+					self.bump();
+					while self.bump().ok_or(ParseError { offset: pipe, message: "unterminated `|`" })? != '|' {}
+				},
+				Some(_) => {
+					self.bump();
+				},
+			}
+		}
+		match self.source[start..self.offset].trim() {
+			"" => self.error("expected a value"),
+			literal => Ok(literal.to_string()),
+		}
+	}
+
+	fn peek(&self) -> Option<char> {
+		self.source[self.offset..].chars().next()
+	}
+
+	fn bump(&mut self) -> Option<char> {
+		let c = self.peek()?;
+		self.offset += c.len_utf8();
+		Some(c)
+	}
+
+	fn skip_whitespace(&mut self) {
+		while self.peek().is_some_and(char::is_whitespace) {
+			self.bump();
+		}
+	}
+
+	fn error<T>(&self, message: &'static str) -> Result<T, ParseError> {
+		Err(ParseError { offset: self.offset, message })
+	}
+
+	/// An error about the character just consumed.
+	fn error_before<T>(&self, message: &'static str) -> Result<T, ParseError> {
+		let length = self.source[..self.offset].chars().next_back().map_or(0, char::len_utf8);
+		Err(ParseError { offset: self.offset - length, message })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use proptest::prelude::*;
+
+	use super::*;
+
+	fn string(string: &str) -> Value {
+		Value::String(string.into())
+	}
+
+	/// Writes a value the way `osascript -ss` does.
+	fn source(value: &Value) -> String {
+		match value {
+			Value::List(values) => format!("{{{}}}", values.iter().map(source).collect::<Vec<_>>().join(", ")),
+			Value::Record(entries) => {
+				let entries: Vec<_> = entries.iter().map(|(label, value)| format!("{}:{}", label, source(value))).collect();
+				format!("{{{}}}", entries.join(", "))
+			},
+			Value::String(string) => {
+				let escaped = string.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\t', "\\t").replace('\r', "\\r");
+				format!("\"{}\"", escaped)
+			},
+			Value::Literal(literal) => literal.clone(),
+		}
+	}
+
+	#[test]
+	fn test_escapes() {
+		assert_eq!(parse(r#"{"C:\\", "next"}"#), Ok(Value::List(vec![string("C:\\"), string("next")])));
+		assert_eq!(parse(r#""a\"b\\c\nd\te\rf\g""#), Ok(string("a\"b\\c\nd\te\rf\\g")));
+		assert_eq!(parse("\"line\nbreak\""), Ok(string("line\nbreak")));
+	}
+
+	#[test]
+	fn test_nesting() {
+		let output = r#"{{"Finder", "Dock"}, {{"Home", missing value}, {}}, {{-1200, 25}}}"#;
+		assert_eq!(parse(output), Ok(Value::List(vec![
+			Value::List(vec![string("Finder"), string("Dock")]),
+			Value::List(vec![Value::List(vec![string("Home"), Value::Literal("missing value".into())]), Value::List(Vec::new())]),
+			Value::List(vec![Value::List(vec![Value::Literal("-1200".into()), Value::Literal("25".into())])]),
+		])));
+	}
+
+	#[test]
+	fn test_records() {
+		let output = r#"{name:"Finder", |unix id|:301, modified:date "Monday, 1 January 2024 at 12:00:00", bounds:{0, 25}}"#;
+		assert_eq!(parse(output), Ok(Value::Record(vec![
+			("name".into(), string("Finder")),
+			("|unix id|".into(), Value::Literal("301".into())),
+			("modified".into(), Value::Literal(r#"date "Monday, 1 January 2024 at 12:00:00""#.into())),
+			("bounds".into(), Value::List(vec![Value::Literal("0".into()), Value::Literal("25".into())])),
+		])));
+	}
+
+	#[test]
+	fn test_errors() {
+		let error = |offset, message| Err(ParseError { offset, message });
+		assert_eq!(parse(""), error(0, "empty output"));
+		assert_eq!(parse(r#"{"a", "b}"#), error(6, "unterminated string"));
+		assert_eq!(parse(r#"{"a\"}"#), error(1, "unterminated string"));
+		assert_eq!(parse(r#"{"a", "b""#), error(0, "unclosed `{`"));
+		assert_eq!(parse("{1, , 2}"), error(4, "expected a value"));
+		assert_eq!(parse(r#"{"a" "b"}"#), error(5, "expected `,` or `}`"));
+		assert_eq!(parse("{a:1, 2}"), error(6, "record mixes labeled and unlabeled items"));
+		assert_eq!(parse("1}"), error(1, "expected the end of the output"));
+		assert_eq!(parse(r#"{"a"}, {"b"}"#), error(5, "expected the end of the output"));
+		assert_eq!(parse("a:1"), error(0, "label outside of a record"));
+		assert_eq!(parse(&"{".repeat(MAX_DEPTH + 1)).map_err(|error| error.message), Err("nesting too deep"));
+	}
+
+	fn value() -> impl Strategy<Value = Value> {
+		let leaf = prop_oneof![
+			any::<String>().prop_map(Value::String),
+			"-?[0-9]{1,6}|true|false|missing value".prop_map(Value::Literal),
+		];
+		leaf.prop_recursive(4, 32, 6, |inner| prop_oneof![
+			prop::collection::vec(inner.clone(), 0..6).prop_map(Value::List),
+			prop::collection::vec(("[a-z][a-z0-9_]{0,8}", inner), 1..6).prop_map(Value::Record),
+		])
+	}
+
+	proptest! {
+		#[test]
+		fn test_round_trip(value in value()) {
+			prop_assert_eq!(parse(&source(&value)), Ok(value));
+		}
+
+		#[test]
+		fn test_arbitrary_input(input in any::<String>()) {
+			if let Err(error) = parse(&input) {
+				prop_assert!(input.is_char_boundary(error.offset));
+			}
+		}
+
+		#[test]
+		fn test_titles_stay_grouped(titles in prop::collection::vec(prop::collection::vec(any::<String>(), 0..4), 0..4)) {
+			let value = Value::List(titles.iter().map(|titles| Value::List(titles.iter().cloned().map(Value::String).collect())).collect());
+			let parsed = parse(&source(&value)).unwrap();
+			prop_assert_eq!(parsed.list().len(), titles.len());
+			prop_assert_eq!(parsed, value);
+		}
+	}
+}
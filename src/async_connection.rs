@@ -0,0 +1,295 @@
+//! The futures returning counterpart of [`Connection`], with the `async` feature.
+
+use std::{collections::VecDeque, convert::TryFrom, pin::Pin};
+#[cfg(all(target_os = "linux", feature = "wayland"))]
+use std::sync::Arc;
+
+use async_io::Timer;
+use futures_lite::{stream, Stream};
+
+#[cfg(target_os = "macos")]
+use crate::apple;
+use crate::connection::Inner;
+#[cfg(any(test, feature = "mock"))]
+use crate::MockConnection;
+#[cfg(all(target_os = "linux", feature = "wayland"))]
+use crate::wayland;
+#[cfg(target_os = "windows")]
+use crate::winapi;
+#[cfg(target_os = "linux")]
+use crate::x11;
+#[cfg(any(not(target_os = "linux"), feature = "wayland", test, feature = "mock"))]
+use crate::ConnectionTrait;
+use crate::{event::{focus, Snapshot, POLL_INTERVAL}, Backend, Connection, Desktop, Error, Result, WindowEvent, WindowInfo, WindowQuery};
+
+/// A stream of window events, it only ends once the backend fails.
+pub type AsyncEvents<'a> = Pin<Box<dyn Stream<Item = Result<WindowEvent>> + Send + 'a>>;
+
+/// A [`Connection`] whose queries return futures instead of blocking the calling thread.
+///
+/// The futures need no particular runtime and run under tokio, async-std or a plain
+/// `block_on` alike. X11 waits for the socket of the connection to become readable between
+/// round trips and the `osascript` helper on macOS runs as an async child process. Wayland
+/// round trips run on a thread pool, Windows and CoreGraphics answer without waiting.
+///
+/// Configure a [`Connection`] first and convert it to keep its settings.
+pub struct AsyncConnection {
+	inner: AsyncInner,
+}
+
+enum AsyncInner {
+	#[cfg(target_os = "linux")]
+	X11(Box<x11::AsyncConnection>),
+	#[cfg(all(target_os = "linux", feature = "wayland"))]
+	Wayland(Arc<wayland::Connection>),
+	#[cfg(target_os = "windows")]
+	Windows(winapi::Connection),
+	#[cfg(target_os = "macos")]
+	MacOS(apple::Connection),
+	#[cfg(any(test, feature = "mock"))]
+	Mock(MockConnection),
+}
+
+impl AsyncConnection {
+	/// Connects like [`Connection::auto`], on a thread pool.
+	pub async fn auto() -> Result<Self> {
+		Self::try_from(blocking::unblock(Connection::auto).await?)
+	}
+
+	/// Connects like [`Connection::with_backend`], on a thread pool.
+	pub async fn with_backend(backend: Backend) -> Result<Self> {
+		Self::try_from(blocking::unblock(move || Connection::with_backend(backend)).await?)
+	}
+
+	pub fn backend(&self) -> Backend {
+		match self.inner {
+			#[cfg(target_os = "linux")]
+			AsyncInner::X11(_) => Backend::X11,
+			#[cfg(all(target_os = "linux", feature = "wayland"))]
+			AsyncInner::Wayland(_) => Backend::Wayland,
+			#[cfg(target_os = "windows")]
+			AsyncInner::Windows(_) => Backend::Windows,
+			#[cfg(target_os = "macos")]
+			AsyncInner::MacOS(_) => Backend::MacOS,
+			#[cfg(any(test, feature = "mock"))]
+			AsyncInner::Mock(_) => Backend::Mock,
+		}
+	}
+
+	pub async fn windows(&self) -> Result<Vec<WindowInfo>> {
+		match &self.inner {
+			#[cfg(target_os = "linux")]
+			AsyncInner::X11(connection) => connection.windows().await,
+			#[cfg(all(target_os = "linux", feature = "wayland"))]
+			AsyncInner::Wayland(connection) => unblock(connection, |connection| connection.windows()).await,
+			#[cfg(target_os = "windows")]
+			AsyncInner::Windows(connection) => connection.windows(),
+			#[cfg(target_os = "macos")]
+			AsyncInner::MacOS(connection) => connection.windows_async().await,
+			#[cfg(any(test, feature = "mock"))]
+			AsyncInner::Mock(connection) => connection.windows(),
+		}
+	}
+
+	/// Every top-level window from the topmost down, see [`ConnectionTrait::windows_stacked`](crate::ConnectionTrait::windows_stacked).
+	pub async fn windows_stacked(&self) -> Result<Vec<WindowInfo>> {
+		match &self.inner {
+			#[cfg(target_os = "linux")]
+			AsyncInner::X11(connection) => connection.windows_stacked().await,
+			#[cfg(all(target_os = "linux", feature = "wayland"))]
+			AsyncInner::Wayland(connection) => connection.windows_stacked(),
+			#[cfg(target_os = "windows")]
+			AsyncInner::Windows(connection) => connection.windows_stacked(),
+			#[cfg(target_os = "macos")]
+			AsyncInner::MacOS(connection) => connection.windows_stacked(),
+			#[cfg(any(test, feature = "mock"))]
+			AsyncInner::Mock(connection) => connection.windows_stacked(),
+		}
+	}
+
+	/// The window that currently has the input focus, if any.
+	pub async fn active_window(&self) -> Result<Option<WindowInfo>> {
+		match &self.inner {
+			#[cfg(target_os = "linux")]
+			AsyncInner::X11(connection) => connection.active_window().await,
+			#[cfg(all(target_os = "linux", feature = "wayland"))]
+			AsyncInner::Wayland(connection) => unblock(connection, |connection| connection.active_window()).await,
+			#[cfg(target_os = "windows")]
+			AsyncInner::Windows(connection) => connection.active_window(),
+			#[cfg(target_os = "macos")]
+			AsyncInner::MacOS(connection) => connection.active_window_async().await,
+			#[cfg(any(test, feature = "mock"))]
+			AsyncInner::Mock(connection) => connection.active_window(),
+		}
+	}
+
+	/// The windows meeting every criterion of `query`, see [`WindowQuery::run`].
+	pub async fn query(&self, query: &WindowQuery) -> Result<Vec<WindowInfo>> {
+		match &self.inner {
+			#[cfg(target_os = "linux")]
+			AsyncInner::X11(connection) => connection.query(query).await,
+			#[allow(unreachable_patterns)]
+			_ => Ok(self.windows().await?.into_iter().filter(|window| query.matches(window)).collect()),
+		}
+	}
+
+	/// The virtual desktops in order, none if the backend has no notion of them.
+	pub async fn desktops(&self) -> Result<Vec<Desktop>> {
+		match &self.inner {
+			#[cfg(target_os = "linux")]
+			AsyncInner::X11(connection) => connection.desktops().await,
+			#[allow(unreachable_patterns)]
+			_ => Ok(Vec::new()),
+		}
+	}
+
+	/// The titles of [`AsyncConnection::windows`], see [`ConnectionTrait::window_titles`](crate::ConnectionTrait::window_titles).
+	pub async fn window_titles(&self) -> Result<Vec<String>> {
+		match &self.inner {
+			#[cfg(target_os = "windows")]
+			AsyncInner::Windows(connection) => connection.window_titles(),
+			#[allow(unreachable_patterns)]
+			_ => Ok(self.windows().await?.into_iter().map(|window| window.title).collect()),
+		}
+	}
+
+	/// Subscribes to windows opening, closing, changing title and gaining focus, like
+	/// [`ConnectionTrait::events`](crate::ConnectionTrait::events).
+	pub async fn events(&self) -> Result<AsyncEvents<'_>> {
+		match &self.inner {
+			#[cfg(target_os = "linux")]
+			AsyncInner::X11(connection) => {
+				if let Some(events) = connection.events().await? {
+					return Ok(Box::pin(stream::unfold(events, |mut events| async move {
+						let event = events.next().await;
+						Some((event, events))
+					})));
+				}
+			},
+			#[cfg(any(test, feature = "mock"))]
+			AsyncInner::Mock(connection) => {
+				connection.check()?;
+				return Ok(Box::pin(stream::unfold(connection, |connection| async move {
+					connection.next_event().map(|event| (event, connection))
+				})));
+			},
+			#[allow(unreachable_patterns)]
+			_ => {},
+		}
+		let events = Poll::new(self).await?;
+		Ok(Box::pin(stream::unfold(events, |mut events| async move {
+			let event = events.next().await;
+			Some((event, events))
+		})))
+	}
+}
+
+impl TryFrom<Connection> for AsyncConnection {
+	type Error = Error;
+	fn try_from(connection: Connection) -> Result<Self> {
+		let inner = match connection.inner {
+			#[cfg(target_os = "linux")]
+			Inner::X11(connection) => AsyncInner::X11(Box::new(x11::AsyncConnection::new(connection)?)),
+			#[cfg(all(target_os = "linux", feature = "wayland"))]
+			Inner::Wayland(connection) => AsyncInner::Wayland(Arc::new(connection)),
+			#[cfg(target_os = "windows")]
+			Inner::Windows(connection) => AsyncInner::Windows(connection),
+			#[cfg(target_os = "macos")]
+			Inner::MacOS(connection) => AsyncInner::MacOS(connection),
+			#[cfg(any(test, feature = "mock"))]
+			Inner::Mock(connection) => AsyncInner::Mock(connection),
+		};
+		Ok(Self { inner })
+	}
+}
+
+#[cfg(any(test, feature = "mock"))]
+impl From<MockConnection> for AsyncConnection {
+	fn from(connection: MockConnection) -> Self {
+		Self { inner: AsyncInner::Mock(connection) }
+	}
+}
+
+/// Runs a blocking Wayland round trip on the thread pool.
+#[cfg(all(target_os = "linux", feature = "wayland"))]
+async fn unblock<T, F>(connection: &Arc<wayland::Connection>, call: F) -> Result<T>
+where
+	T: Send + 'static,
+	F: FnOnce(&wayland::Connection) -> Result<T> + Send + 'static,
+{
+	let connection = Arc::clone(connection);
+	blocking::unblock(move || call(&connection)).await
+}
+
+/// Diffs snapshots taken every [`POLL_INTERVAL`], waiting on a timer rather than sleeping.
+struct Poll<'a> {
+	connection: &'a AsyncConnection,
+	snapshot: Snapshot,
+	pending: VecDeque<WindowEvent>,
+}
+
+impl<'a> Poll<'a> {
+	async fn new(connection: &'a AsyncConnection) -> Result<Self> {
+		let snapshot = Snapshot::new(connection.windows().await?, focus(connection.active_window().await)?);
+		Ok(Self { connection, snapshot, pending: VecDeque::new() })
+	}
+
+	async fn next(&mut self) -> Result<WindowEvent> {
+		loop {
+			if let Some(event) = self.pending.pop_front() {
+				return Ok(event);
+			}
+			Timer::after(POLL_INTERVAL).await;
+			let windows = self.connection.windows().await?;
+			let active = focus(self.connection.active_window().await)?;
+			self.pending.extend(self.snapshot.windows(windows));
+			self.pending.extend(self.snapshot.active(active));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use futures_lite::{future::block_on, StreamExt};
+
+	use super::*;
+	use crate::mock::window;
+
+	#[test]
+	fn test_queries() {
+		let mock = MockConnection::with_windows(vec![window(1, "a"), window(2, "b")]);
+		mock.set_active(Some(2));
+		let connection = AsyncConnection::try_from(Connection::from(mock)).unwrap();
+		assert_eq!(connection.backend(), Backend::Mock);
+		block_on(async {
+			assert_eq!(connection.window_titles().await, Ok(vec!["a".to_string(), "b".to_string()]));
+			assert_eq!(connection.active_window().await, Ok(Some(window(2, "b"))));
+			assert_eq!(connection.query(&WindowQuery::new().title_contains("a")).await, Ok(vec![window(1, "a")]));
+			assert_eq!(connection.desktops().await, Ok(Vec::new()));
+		});
+	}
+
+	#[test]
+	fn test_events() {
+		let mock = MockConnection::default();
+		mock.push_event(WindowEvent::Opened(window(1, "a")));
+		mock.push_event(WindowEvent::FocusChanged(Some(window(1, "a"))));
+		let connection = AsyncConnection::from(mock);
+		let events: Vec<_> = block_on(async { connection.events().await.unwrap().collect().await });
+		assert_eq!(events, vec![
+			Ok(WindowEvent::Opened(window(1, "a"))),
+			Ok(WindowEvent::FocusChanged(Some(window(1, "a")))),
+		]);
+		assert_eq!(block_on(connection.windows()), Ok(vec![window(1, "a")]));
+	}
+
+	#[test]
+	fn test_runtimes() {
+		let connection = AsyncConnection::from(MockConnection::with_windows(vec![window(1, "a")]));
+		let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+		assert_eq!(runtime.block_on(connection.window_titles()), Ok(vec!["a".to_string()]));
+		assert_eq!(async_std::task::block_on(connection.window_titles()), Ok(vec!["a".to_string()]));
+		let connection = runtime.block_on(AsyncConnection::with_backend(Backend::Mock)).unwrap();
+		assert_eq!(async_std::task::block_on(connection.windows()), Ok(Vec::new()));
+	}
+}
@@ -0,0 +1,213 @@
+//! `window-titles`, listing, filtering and following the windows of the session from the shell.
+
+mod output;
+
+use std::{error::Error as StdError, io, process::ExitCode};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use regex::Regex;
+use window_titles::{Backend, Connection, ConnectionTrait, WindowEvent, WindowQuery};
+
+use output::{Format, Writer};
+
+#[derive(Debug, Parser)]
+#[command(name = "window-titles", version, about = "Lists the windows of the desktop session")]
+struct Cli {
+	#[command(subcommand)]
+	command: Command,
+	/// The windowing system to ask, the first one available if left out.
+	#[arg(long, value_enum, global = true)]
+	backend: Option<BackendArg>,
+	/// The X display to connect to instead of $DISPLAY.
+	#[cfg(target_os = "linux")]
+	#[arg(long, global = true, conflicts_with = "backend")]
+	display: Option<String>,
+	/// How windows and events are printed.
+	#[arg(long, short, value_enum, default_value_t = Format::Plain, global = true)]
+	format: Format,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+	/// Lists the windows meeting the filters.
+	List {
+		#[command(flatten)]
+		filter: Filter,
+		/// Lists the topmost window first instead of in the order the platform lists them.
+		#[arg(long)]
+		stacked: bool,
+	},
+	/// Shows the window holding the input focus, exiting with 1 if there is none.
+	Active,
+	/// Prints windows opening, closing, changing title and gaining focus until interrupted.
+	Watch {
+		#[command(flatten)]
+		filter: Filter,
+	},
+}
+
+#[derive(Debug, Args)]
+struct Filter {
+	/// Only windows whose title contains this text.
+	#[arg(long)]
+	title: Option<String>,
+	/// Only windows whose title matches this regular expression.
+	#[arg(long, value_name = "REGEX", conflicts_with = "title")]
+	title_regex: Option<Regex>,
+	/// Only windows of this application class.
+	#[arg(long)]
+	class: Option<String>,
+	/// Only windows owned by this process.
+	#[arg(long)]
+	pid: Option<u32>,
+	/// Only windows on this virtual desktop, or shown on all of them.
+	#[arg(long)]
+	desktop: Option<u32>,
+	/// Only windows mapped on screen.
+	#[arg(long)]
+	visible: bool,
+	/// Only windows a taskbar would show.
+	#[arg(long)]
+	taskbar: bool,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum BackendArg {
+	X11,
+	Wayland,
+	Windows,
+	#[value(name = "macos")]
+	MacOS,
+}
+
+impl From<BackendArg> for Backend {
+	fn from(backend: BackendArg) -> Self {
+		match backend {
+			BackendArg::X11 => Backend::X11,
+			BackendArg::Wayland => Backend::Wayland,
+			BackendArg::Windows => Backend::Windows,
+			BackendArg::MacOS => Backend::MacOS,
+		}
+	}
+}
+
+impl Filter {
+	fn query(&self) -> WindowQuery {
+		let mut query = WindowQuery::new();
+		if let Some(title) = &self.title {
+			query = query.title_contains(title.as_str());
+		}
+		if let Some(regex) = &self.title_regex {
+			query = query.title_matches(regex.clone());
+		}
+		if let Some(class) = &self.class {
+			query = query.class(class.as_str());
+		}
+		if let Some(pid) = self.pid {
+			query = query.pid(pid);
+		}
+		if let Some(desktop) = self.desktop {
+			query = query.desktop(desktop);
+		}
+		if self.visible {
+			query = query.visible_only();
+		}
+		if self.taskbar {
+			query = query.taskbar_only();
+		}
+		query
+	}
+}
+
+fn main() -> ExitCode {
+	match run(Cli::parse()) {
+		Ok(code) => code,
+		// The reader went away, as `head` does once it has seen enough.
+		Err(error) if error.downcast_ref::<io::Error>().is_some_and(|error| error.kind() == io::ErrorKind::BrokenPipe) => ExitCode::SUCCESS,
+		Err(error) => {
+			eprintln!("window-titles: {}", error);
+			ExitCode::from(2)
+		},
+	}
+}
+
+fn run(cli: Cli) -> Result<ExitCode, Box<dyn StdError>> {
+	let connection = connect(&cli)?;
+	let mut writer = Writer::new(io::stdout().lock(), cli.format);
+	match cli.command {
+		Command::List { filter, stacked: false } => writer.windows(&filter.query().run(&connection)?)?,
+		Command::List { filter, stacked: true } => {
+			let query = filter.query();
+			let windows: Vec<_> = connection.windows_stacked()?.into_iter().filter(|window| query.matches(window)).collect();
+			writer.windows(&windows)?;
+		},
+		Command::Active => {
+			let active = connection.active_window()?;
+			writer.window(active.as_ref())?;
+			if active.is_none() {
+				return Ok(ExitCode::FAILURE);
+			}
+		},
+		Command::Watch { filter } => {
+			let query = filter.query();
+			for event in connection.events()? {
+				let event = event?;
+				if shown(&query, &event) {
+					writer.event(&event)?;
+				}
+			}
+		},
+	}
+	Ok(ExitCode::SUCCESS)
+}
+
+fn connect(cli: &Cli) -> window_titles::Result<Connection> {
+	#[cfg(target_os = "linux")]
+	if let Some(display) = &cli.display {
+		return Connection::connect_to(display);
+	}
+	match cli.backend {
+		Some(backend) => Connection::with_backend(backend.into()),
+		None => Connection::auto(),
+	}
+}
+
+/// Whether an event concerns a window meeting the query, losing the focus always does.
+fn shown(query: &WindowQuery, event: &WindowEvent) -> bool {
+	match event {
+		WindowEvent::Opened(window) | WindowEvent::Closed(window) | WindowEvent::TitleChanged(window) => query.matches(window),
+		WindowEvent::FocusChanged(window) => window.as_ref().is_none_or(|window| query.matches(window)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use clap::CommandFactory;
+	use window_titles::WindowInfo;
+
+	use super::*;
+
+	#[test]
+	fn test_arguments() {
+		Cli::command().debug_assert();
+		let cli = Cli::try_parse_from(["window-titles", "list", "--class", "Gedit", "--visible", "-f", "json"]).unwrap();
+		assert_eq!(cli.format, Format::Json);
+		assert!(matches!(cli.command, Command::List { stacked: false, .. }));
+		assert!(Cli::try_parse_from(["window-titles", "list", "--title", "a", "--title-regex", "b"]).is_err());
+		assert!(Cli::try_parse_from(["window-titles", "list", "--title-regex", "("]).is_err());
+	}
+
+	#[test]
+	fn test_shown() {
+		let cli = Cli::try_parse_from(["window-titles", "watch", "--title-regex", "^a"]).unwrap();
+		let query = match cli.command {
+			Command::Watch { filter } => filter.query(),
+			_ => unreachable!(),
+		};
+		let window = |title: &str| WindowInfo { title: title.into(), ..WindowInfo::default() };
+		assert!(shown(&query, &WindowEvent::Opened(window("ab"))));
+		assert!(!shown(&query, &WindowEvent::TitleChanged(window("ba"))));
+		assert!(!shown(&query, &WindowEvent::FocusChanged(Some(window("b")))));
+		assert!(shown(&query, &WindowEvent::FocusChanged(None)));
+	}
+}
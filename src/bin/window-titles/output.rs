@@ -0,0 +1,236 @@
+//! Writes window records and events in the formats the tool offers.
+
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+use serde::Serialize;
+use window_titles::{WindowEvent, WindowInfo};
+
+/// The columns of CSV and TSV output, named after the keys of the JSON schema with the
+/// geometry spread over four columns.
+const COLUMNS: [&str; 15] = [
+	"id", "title", "title_source", "pid", "class", "instance", "visible", "state", "window_type",
+	"x", "y", "width", "height", "screen", "desktop",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+	/// One window per line: the id, class and title.
+	Plain,
+	/// An array of window records in the JSON schema of the `serde` feature, or one event per line when watching.
+	Json,
+	/// Comma separated values with a header, quoted where needed.
+	Csv,
+	/// Tab separated values with a header, tabs and newlines escaped with backslashes.
+	Tsv,
+}
+
+/// A field of a window record.
+enum Value {
+	Null,
+	Bool(bool),
+	Number(i128),
+	Text(String),
+	List(Vec<String>),
+}
+
+pub struct Writer<W> {
+	out: W,
+	format: Format,
+	/// Whether the CSV or TSV header of the event stream has been written.
+	header: bool,
+}
+
+impl<W: Write> Writer<W> {
+	pub fn new(out: W, format: Format) -> Self {
+		Self { out, format, header: false }
+	}
+
+	pub fn windows(&mut self, windows: &[WindowInfo]) -> io::Result<()> {
+		match self.format {
+			Format::Plain => windows.iter().try_for_each(|window| writeln!(self.out, "{}", plain(window)))?,
+			Format::Json => self.json(&windows)?,
+			Format::Csv | Format::Tsv => {
+				self.row(None, &COLUMNS.map(String::from))?;
+				for window in windows {
+					self.row(None, &fields(window).map(|value| self.cell(value)))?;
+				}
+			},
+		}
+		self.out.flush()
+	}
+
+	/// A single window, as the active one, writing `null` or just the header if there is none.
+	pub fn window(&mut self, window: Option<&WindowInfo>) -> io::Result<()> {
+		match self.format {
+			Format::Json => self.json(&window)?,
+			_ => return self.windows(window.map_or(&[][..], std::slice::from_ref)),
+		}
+		self.out.flush()
+	}
+
+	/// An event of the stream, flushed right away so that pipes see it as it happens.
+	pub fn event(&mut self, event: &WindowEvent) -> io::Result<()> {
+		// Named as in the `event` key of the JSON schema.
+		let (kind, window) = match event {
+			WindowEvent::Opened(window) => ("opened", Some(window)),
+			WindowEvent::Closed(window) => ("closed", Some(window)),
+			WindowEvent::TitleChanged(window) => ("title_changed", Some(window)),
+			WindowEvent::FocusChanged(window) => ("focus_changed", window.as_ref()),
+		};
+		match self.format {
+			Format::Plain => writeln!(self.out, "{:<13} {}", kind, window.map_or_else(|| "-".to_string(), plain))?,
+			Format::Json => self.json(event)?,
+			Format::Csv | Format::Tsv => {
+				if !self.header {
+					self.row(Some("event"), &COLUMNS.map(String::from))?;
+					self.header = true;
+				}
+				let cells = match window {
+					Some(window) => fields(window).map(|value| self.cell(value)),
+					None => COLUMNS.map(|_| String::new()),
+				};
+				self.row(Some(kind), &cells)?;
+			},
+		}
+		self.out.flush()
+	}
+
+	fn json(&mut self, value: &impl Serialize) -> io::Result<()> {
+		serde_json::to_writer(&mut self.out, value)?;
+		writeln!(self.out)
+	}
+
+	fn row(&mut self, first: Option<&str>, cells: &[String]) -> io::Result<()> {
+		let separator = if self.format == Format::Tsv { "\t" } else { "," };
+		let row: Vec<_> = first.into_iter().map(|cell| self.escape(cell)).chain(cells.iter().cloned()).collect();
+		writeln!(self.out, "{}", row.join(separator))
+	}
+
+	fn cell(&self, value: Value) -> String {
+		match value {
+			Value::Null => String::new(),
+			Value::Bool(value) => value.to_string(),
+			Value::Number(value) => value.to_string(),
+			Value::Text(text) => self.escape(&text),
+			Value::List(values) => self.escape(&values.join("|")),
+		}
+	}
+
+	fn escape(&self, text: &str) -> String {
+		match self.format {
+			Format::Tsv => text.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r"),
+			_ if text.contains(['"', ',', '\n', '\r']) => format!("\"{}\"", text.replace('"', "\"\"")),
+			_ => text.to_string(),
+		}
+	}
+}
+
+fn fields(window: &WindowInfo) -> [Value; 15] {
+	let text = |text: &Option<String>| text.clone().map_or(Value::Null, Value::Text);
+	let number = |number: Option<i128>| number.map_or(Value::Null, Value::Number);
+	let geometry = window.geometry;
+	[
+		Value::Number(window.id.into()),
+		Value::Text(window.title.clone()),
+		serialized(window.title_source),
+		number(window.pid.map(i128::from)),
+		text(&window.class),
+		text(&window.instance),
+		Value::Bool(window.visible),
+		serialized(window.state),
+		serialized(window.window_type),
+		number(geometry.map(|geometry| geometry.x.into())),
+		number(geometry.map(|geometry| geometry.y.into())),
+		number(geometry.map(|geometry| geometry.width.into())),
+		number(geometry.map(|geometry| geometry.height.into())),
+		number(window.screen.map(|screen| screen as i128)),
+		number(window.desktop.map(i128::from)),
+	]
+}
+
+/// A field named as in the JSON schema, such as `dropdown_menu` or `["maximized", "urgent"]`.
+fn serialized(value: impl Serialize) -> Value {
+	match serde_json::to_value(value) {
+		Ok(serde_json::Value::String(name)) => Value::Text(name),
+		Ok(serde_json::Value::Array(names)) => Value::List(names.iter().filter_map(|name| name.as_str().map(String::from)).collect()),
+		_ => Value::Null,
+	}
+}
+
+fn plain(window: &WindowInfo) -> String {
+	format!("{:#010x} {:<20} {}", window.id, window.class.as_deref().unwrap_or("-"), window.title)
+}
+
+#[cfg(test)]
+mod tests {
+	use window_titles::{Geometry, WindowState, WindowType};
+
+	use super::*;
+
+	fn window() -> WindowInfo {
+		WindowInfo {
+			id: 0x3a00007,
+			title: "a, \"b\"\tc".into(),
+			pid: Some(42),
+			class: Some("Gedit".into()),
+			visible: true,
+			state: WindowState::MAXIMIZED | WindowState::URGENT,
+			window_type: WindowType::DropdownMenu,
+			geometry: Some(Geometry { x: -5, y: 10, width: 800, height: 600 }),
+			..WindowInfo::default()
+		}
+	}
+
+	fn write(format: Format, write: impl FnOnce(&mut Writer<&mut Vec<u8>>) -> io::Result<()>) -> String {
+		let mut out = Vec::new();
+		write(&mut Writer::new(&mut out, format)).unwrap();
+		String::from_utf8(out).unwrap()
+	}
+
+	#[test]
+	fn test_plain() {
+		assert_eq!(write(Format::Plain, |writer| writer.windows(&[window()])), "0x03a00007 Gedit                a, \"b\"\tc\n");
+		assert_eq!(write(Format::Plain, |writer| writer.event(&WindowEvent::FocusChanged(None))), "focus_changed -\n");
+	}
+
+	#[test]
+	fn test_json() {
+		let expected = serde_json::to_value(window()).unwrap();
+		let output = write(Format::Json, |writer| writer.windows(&[window()]));
+		assert_eq!(serde_json::from_str::<serde_json::Value>(&output).unwrap(), serde_json::Value::Array(vec![expected.clone()]));
+		let output = write(Format::Json, |writer| writer.window(Some(&window())));
+		assert_eq!(serde_json::from_str::<serde_json::Value>(&output).unwrap(), expected);
+		assert_eq!(write(Format::Json, |writer| writer.window(None)), "null\n");
+		assert_eq!(write(Format::Json, |writer| writer.event(&WindowEvent::FocusChanged(None))), "{\"event\":\"focus_changed\",\"window\":null}\n");
+	}
+
+	#[test]
+	fn test_csv() {
+		let output = write(Format::Csv, |writer| writer.windows(&[window()]));
+		assert_eq!(output.lines().nth(1), Some(r#"60817415,"a, ""b""	c",native,42,Gedit,,true,maximized|urgent,dropdown_menu,-5,10,800,600,,"#));
+		let output = write(Format::Csv, |writer| {
+			writer.event(&WindowEvent::Closed(window()))?;
+			writer.event(&WindowEvent::FocusChanged(None))
+		});
+		let lines: Vec<_> = output.lines().collect();
+		assert_eq!(lines.len(), 3);
+		assert!(lines[0].starts_with("event,id,title,"));
+		assert_eq!(lines[2], "focus_changed,,,,,,,,,,,,,,,");
+	}
+
+	#[test]
+	fn test_tsv() {
+		let output = write(Format::Tsv, |writer| writer.windows(&[window()]));
+		assert_eq!(output.lines().next(), Some(COLUMNS.join("\t").as_str()));
+		assert!(output.lines().nth(1).unwrap().starts_with("60817415\ta, \"b\"\\tc\tnative\t42\t"));
+	}
+
+	#[test]
+	fn test_columns() {
+		let json = serde_json::to_value(window()).unwrap();
+		for column in COLUMNS {
+			assert!(json.get(column).or_else(|| json["geometry"].get(column)).is_some(), "{} is not part of the schema", column);
+		}
+	}
+}
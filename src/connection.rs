@@ -0,0 +1,268 @@
+//! The connection handed out to users, wrapping whichever backend was chosen at runtime.
+
+#[cfg(target_os = "macos")]
+use crate::apple;
+#[cfg(any(test, feature = "mock"))]
+use crate::MockConnection;
+#[cfg(all(target_os = "linux", feature = "wayland"))]
+use crate::wayland;
+#[cfg(target_os = "windows")]
+use crate::winapi;
+#[cfg(target_os = "linux")]
+use crate::x11::{self, Discovery};
+use crate::{ConnectionTrait, Desktop, Error, Events, Result, WindowInfo, WindowQuery};
+
+/// A windowing system to list windows from.
+///
+/// Every variant exists on every platform, connecting to one that is not available
+/// in this build fails with [`Error::BackendUnavailable`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Backend {
+	/// An X server, including XWayland, through `xcb`.
+	X11,
+	/// A Wayland compositor offering a foreign toplevel protocol, with the `wayland` feature.
+	Wayland,
+	/// The Win32 window list.
+	Windows,
+	/// System Events through `osascript`, or CoreGraphics with the `core-graphics` feature.
+	MacOS,
+	/// An empty [`MockConnection`], with the `mock` feature.
+	Mock,
+}
+
+impl Backend {
+	/// The backends [`Connection::auto`] tries on this platform, in order. Wayland is
+	/// only tried when `WAYLAND_DISPLAY` is set.
+	pub fn probe_order() -> &'static [Backend] {
+		if cfg!(target_os = "linux") {
+			&[Backend::Wayland, Backend::X11]
+		} else if cfg!(target_os = "windows") {
+			&[Backend::Windows]
+		} else if cfg!(target_os = "macos") {
+			&[Backend::MacOS]
+		} else {
+			&[]
+		}
+	}
+}
+
+pub struct Connection {
+	pub(crate) inner: Inner,
+}
+
+pub(crate) enum Inner {
+	#[cfg(target_os = "linux")]
+	X11(x11::Connection),
+	#[cfg(all(target_os = "linux", feature = "wayland"))]
+	Wayland(wayland::Connection),
+	#[cfg(target_os = "windows")]
+	Windows(winapi::Connection),
+	#[cfg(target_os = "macos")]
+	MacOS(apple::Connection),
+	#[cfg(any(test, feature = "mock"))]
+	Mock(MockConnection),
+}
+
+macro_rules! dispatch {
+	($inner:expr, $connection:ident => $call:expr) => {
+		match $inner {
+			#[cfg(target_os = "linux")]
+			Inner::X11($connection) => $call,
+			#[cfg(all(target_os = "linux", feature = "wayland"))]
+			Inner::Wayland($connection) => $call,
+			#[cfg(target_os = "windows")]
+			Inner::Windows($connection) => $call,
+			#[cfg(target_os = "macos")]
+			Inner::MacOS($connection) => $call,
+			#[cfg(any(test, feature = "mock"))]
+			Inner::Mock($connection) => $call,
+		}
+	};
+}
+
+impl Connection {
+	/// Connects to the first backend of [`Backend::probe_order`] that accepts, returning
+	/// the error of the last one tried if none does.
+	pub fn auto() -> Result<Self> {
+		Self::probe(Backend::probe_order())
+	}
+
+	fn probe(backends: &[Backend]) -> Result<Self> {
+		let mut last_error = None;
+		for &backend in backends {
+			if backend == Backend::Wayland && std::env::var_os("WAYLAND_DISPLAY").is_none() {
+				continue;
+			}
+			match Self::with_backend(backend) {
+				Ok(connection) => return Ok(connection),
+				Err(error) => last_error = Some(error),
+			}
+		}
+		Err(last_error.unwrap_or_else(|| Error::ConnectionFailed("no backend to try on this platform".into())))
+	}
+
+	/// Connects to the given backend only.
+	pub fn with_backend(backend: Backend) -> Result<Self> {
+		let inner = match backend {
+			#[cfg(target_os = "linux")]
+			Backend::X11 => Inner::X11(x11::Connection::new()?),
+			#[cfg(all(target_os = "linux", feature = "wayland"))]
+			Backend::Wayland => Inner::Wayland(wayland::Connection::new()?),
+			#[cfg(target_os = "windows")]
+			Backend::Windows => Inner::Windows(winapi::Connection::new()?),
+			#[cfg(target_os = "macos")]
+			Backend::MacOS => Inner::MacOS(apple::Connection::new()?),
+			#[cfg(any(test, feature = "mock"))]
+			Backend::Mock => Inner::Mock(MockConnection::default()),
+			#[allow(unreachable_patterns)]
+			backend => return Err(Error::BackendUnavailable(backend)),
+		};
+		Ok(Self { inner })
+	}
+
+	/// Connects to the X server named by `display`, such as `:1` or `host:0`, rather than `$DISPLAY`.
+	/// The screen given in the name only makes it the default one, every screen is still
+	/// enumerated unless restricted with [`Connection::set_screen`].
+	#[cfg(target_os = "linux")]
+	pub fn connect_to(display: &str) -> Result<Self> {
+		Ok(Self { inner: Inner::X11(x11::Connection::connect(Some(display))?) })
+	}
+
+	/// The backend this connection was made to.
+	pub fn backend(&self) -> Backend {
+		match self.inner {
+			#[cfg(target_os = "linux")]
+			Inner::X11(_) => Backend::X11,
+			#[cfg(all(target_os = "linux", feature = "wayland"))]
+			Inner::Wayland(_) => Backend::Wayland,
+			#[cfg(target_os = "windows")]
+			Inner::Windows(_) => Backend::Windows,
+			#[cfg(target_os = "macos")]
+			Inner::MacOS(_) => Backend::MacOS,
+			#[cfg(any(test, feature = "mock"))]
+			Inner::Mock(_) => Backend::Mock,
+		}
+	}
+
+	/// Limits how many bytes, rounded down to a multiple of four, are read from a single
+	/// X11 property such as a title or the client list. Longer values are cut off. Defaults to 1 MiB.
+	/// Fails with [`Error::Unsupported`] on other backends.
+	#[cfg(target_os = "linux")]
+	pub fn set_max_property_size(&mut self, bytes: u32) -> Result<()> {
+		match &mut self.inner {
+			Inner::X11(connection) => {
+				connection.set_max_property_size(bytes);
+				Ok(())
+			},
+			#[allow(unreachable_patterns)]
+			_ => Err(Error::Unsupported("X11 properties")),
+		}
+	}
+
+	/// Restricts the X11 backend to the screen with the given index, as reported in
+	/// [`WindowInfo::screen`], rather than every root window. `None` lists all screens again.
+	/// Fails with [`Error::NoSuchScreen`] if the display has no such screen and with
+	/// [`Error::Unsupported`] on other backends.
+	#[cfg(target_os = "linux")]
+	pub fn set_screen(&mut self, screen: Option<usize>) -> Result<()> {
+		match &mut self.inner {
+			Inner::X11(connection) => connection.set_screen(screen),
+			#[allow(unreachable_patterns)]
+			_ => Err(Error::Unsupported("screens apart")),
+		}
+	}
+
+	/// How the X11 backend finds the client windows, `None` with any other backend.
+	#[cfg(target_os = "linux")]
+	pub fn discovery(&self) -> Option<Discovery> {
+		match &self.inner {
+			Inner::X11(connection) => Some(connection.discovery()),
+			#[allow(unreachable_patterns)]
+			_ => None,
+		}
+	}
+}
+
+impl ConnectionTrait for Connection {
+	/// Same as [`Connection::auto`].
+	fn new() -> Result<Self> {
+		Self::auto()
+	}
+	fn windows(&self) -> Result<Vec<WindowInfo>> {
+		dispatch!(&self.inner, connection => connection.windows())
+	}
+	fn windows_stacked(&self) -> Result<Vec<WindowInfo>> {
+		dispatch!(&self.inner, connection => connection.windows_stacked())
+	}
+	fn active_window(&self) -> Result<Option<WindowInfo>> {
+		dispatch!(&self.inner, connection => connection.active_window())
+	}
+	fn query(&self, query: &WindowQuery) -> Result<Vec<WindowInfo>> {
+		dispatch!(&self.inner, connection => connection.query(query))
+	}
+	fn desktops(&self) -> Result<Vec<Desktop>> {
+		dispatch!(&self.inner, connection => connection.desktops())
+	}
+	fn window_titles(&self) -> Result<Vec<String>> {
+		dispatch!(&self.inner, connection => connection.window_titles())
+	}
+	fn events(&self) -> Result<Events<'_>> {
+		dispatch!(&self.inner, connection => connection.events())
+	}
+}
+
+#[cfg(any(test, feature = "mock"))]
+impl From<MockConnection> for Connection {
+	fn from(connection: MockConnection) -> Self {
+		Self { inner: Inner::Mock(connection) }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mock;
+
+	#[test]
+	fn test_with_backend() {
+		let connection = Connection::with_backend(Backend::Mock).unwrap();
+		assert_eq!(connection.backend(), Backend::Mock);
+		assert_eq!(connection.windows(), Ok(Vec::new()));
+		#[cfg(not(target_os = "windows"))]
+		assert_eq!(Connection::with_backend(Backend::Windows).err(), Some(Error::BackendUnavailable(Backend::Windows)));
+	}
+
+	#[test]
+	fn test_probe() {
+		assert_eq!(Connection::probe(&[]).err(), Some(Error::ConnectionFailed("no backend to try on this platform".into())));
+		#[cfg(not(target_os = "windows"))]
+		{
+			assert_eq!(Connection::probe(&[Backend::Windows]).err(), Some(Error::BackendUnavailable(Backend::Windows)));
+			assert_eq!(Connection::probe(&[Backend::Windows, Backend::Mock]).map(|connection| connection.backend()), Ok(Backend::Mock));
+		}
+	}
+
+	#[test]
+	fn test_from_mock() {
+		let mock = MockConnection::with_windows(vec![mock::window(1, "a")]);
+		let connection = Connection::from(mock);
+		assert_eq!(connection.window_titles(), Ok(vec!["a".to_string()]));
+		assert!(!Backend::probe_order().contains(&Backend::Mock));
+	}
+
+	#[cfg(target_os = "linux")]
+	#[test]
+	fn test_x11_settings() {
+		let mut connection = Connection::from(MockConnection::default());
+		assert_eq!(connection.set_screen(Some(0)), Err(Error::Unsupported("screens apart")));
+		assert_eq!(connection.set_max_property_size(4096), Err(Error::Unsupported("X11 properties")));
+	}
+
+	#[cfg(target_os = "linux")]
+	#[test]
+	fn test_connect_to() {
+		assert!(matches!(Connection::connect_to(":4711"), Err(Error::ConnectionFailed(_))));
+		assert!(matches!(Connection::connect_to("not a display"), Err(Error::ConnectionFailed(_))));
+	}
+}
@@ -0,0 +1,115 @@
+//! Decoders for the legacy text encodings X11 clients store in `WM_NAME`.
+
+const ESC: u8 = 0x1b;
+const CSI: u8 = 0x9b;
+
+/// Decodes ISO 8859-1, which maps every byte onto the code point of the same value.
+pub(crate) fn latin1(bytes: &[u8]) -> String {
+	bytes.iter().map(|&byte| char::from(byte)).collect()
+}
+
+/// The character set designated to the left (GL) or right (GR) half of the code table.
+#[derive(Clone, Copy, PartialEq)]
+enum Charset {
+	Ascii,
+	Latin1,
+	/// Any set without a decoder here, spanning `width` bytes per character.
+	Unsupported { width: usize },
+}
+
+/// Decodes `COMPOUND_TEXT`, the ISO 2022 subset described in the X Consortium
+/// Compound Text Encoding standard.
+///
+/// ASCII, the right half of ISO 8859-1 and UTF-8 segments are decoded, every
+/// character from another set becomes U+FFFD.
+pub(crate) fn compound_text(bytes: &[u8]) -> String {
+	let mut text = String::with_capacity(bytes.len());
+	let (mut left, mut right) = (Charset::Ascii, Charset::Latin1);
+	let mut index = 0;
+	while index < bytes.len() {
+		let byte = bytes[index];
+		index += 1;
+		match byte {
+			ESC => {
+				let (intermediates, end) = escape_sequence(&bytes[index..]);
+				let final_byte = bytes.get(index + end - 1).copied().unwrap_or(0);
+				index += end;
+				match (intermediates, final_byte) {
+					(b"%", b'G') => {
+						let length = find_sequence(&bytes[index..], b"\x1b%@").unwrap_or(bytes.len() - index);
+						text.push_str(&String::from_utf8_lossy(&bytes[index..index + length]));
+						index = (index + length + 3).min(bytes.len());
+					},
+					(b"%/", _) => {
+						// Extended segment: two length bytes, then the segment itself.
+						if let [high, low, ..] = bytes[index..] {
+							let length = usize::from(high & 0x7f) * 128 + usize::from(low & 0x7f);
+							index = (index + 2 + length).min(bytes.len());
+						} else {
+							index = bytes.len();
+						}
+						text.push(char::REPLACEMENT_CHARACTER);
+					},
+					(b"(", b'B') | (b"(", b'J') => left = Charset::Ascii,
+					(b"-", b'A') => right = Charset::Latin1,
+					(b"(", _) => left = Charset::Unsupported { width: 1 },
+					(b")", _) | (b"-", _) => right = Charset::Unsupported { width: 1 },
+					(b"$(", _) => left = Charset::Unsupported { width: 2 },
+					(b"$)", _) => right = Charset::Unsupported { width: 2 },
+					_ => {},
+				}
+			},
+			CSI => {
+				// Direction changes carry no text, skip up to the final byte.
+				while index < bytes.len() && !(0x40..=0x7e).contains(&bytes[index]) {
+					index += 1;
+				}
+				index += 1;
+			},
+			b'\t' | b'\n' => text.push(char::from(byte)),
+			0x20..=0x7f | 0xa0..=0xff => {
+				let charset = if byte < 0x80 { left } else { right };
+				match charset {
+					Charset::Ascii | Charset::Latin1 => text.push(char::from(byte)),
+					Charset::Unsupported { width } => {
+						text.push(char::REPLACEMENT_CHARACTER);
+						index = (index + width - 1).min(bytes.len());
+					},
+				}
+			},
+			_ => {},
+		}
+	}
+	text
+}
+
+/// Splits the bytes after an `ESC` into its intermediate bytes and the length up to and including the final byte.
+fn escape_sequence(bytes: &[u8]) -> (&[u8], usize) {
+	let intermediates = bytes.iter().take_while(|byte| (0x20..=0x2f).contains(*byte)).count();
+	(&bytes[..intermediates], (intermediates + 1).min(bytes.len()))
+}
+
+fn find_sequence(bytes: &[u8], sequence: &[u8]) -> Option<usize> {
+	bytes.windows(sequence.len()).position(|window| window == sequence)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_latin1() {
+		assert_eq!(latin1(b"caf\xe9 \xbd"), "café ½");
+	}
+
+	#[test]
+	fn test_compound_text() {
+		assert_eq!(compound_text(b"plain"), "plain");
+		assert_eq!(compound_text(b"caf\xe9"), "café");
+		assert_eq!(compound_text(b"\x1b%G\xe2\x9c\x93\x1b%@ done"), "✓ done");
+		assert_eq!(compound_text(b"a\x1b$)A\xb0\xa1b"), "a\u{fffd}b");
+		assert_eq!(compound_text(b"\x1b-A\xe9\x1b-B\xe9\x1b-A\xe9"), "é\u{fffd}é");
+		assert_eq!(compound_text(b"\x9b1]left\x9b]"), "left");
+		assert_eq!(compound_text(b"cut\x1b"), "cut");
+	}
+}
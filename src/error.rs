@@ -0,0 +1,45 @@
+use std::{error, fmt};
+
+use crate::Backend;
+
+/// Everything that can go wrong while querying windows, independent of the backend.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+	/// The connection to the windowing system could not be established or was lost.
+	ConnectionFailed(String),
+	/// The windowing system rejected a request, such as one naming an atom or window it does not know.
+	Protocol(String),
+	/// The window manager does not provide the named EWMH hint.
+	MissingEwmh(&'static str),
+	/// The display has no screen with the given index.
+	NoSuchScreen(usize),
+	/// The backend cannot tell the named information, such as the focus on some Wayland compositors.
+	Unsupported(&'static str),
+	/// The platform refused access to the window list.
+	PermissionDenied,
+	/// The helper process queried for windows could not be run or failed.
+	HelperProcessFailed(String),
+	/// A title or other property could not be decoded.
+	Decode(String),
+	/// The backend is not supported on this platform or was not enabled at build time.
+	BackendUnavailable(Backend),
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::ConnectionFailed(reason) => write!(fmt, "Connection to the windowing system failed: {}", reason),
+			Error::Protocol(reason) => write!(fmt, "The windowing system rejected a request: {}", reason),
+			Error::MissingEwmh(hint) => write!(fmt, "The window manager does not support {}", hint),
+			Error::NoSuchScreen(screen) => write!(fmt, "The display has no screen {}", screen),
+			Error::Unsupported(feature) => write!(fmt, "The windowing system does not tell {}", feature),
+			Error::PermissionDenied => write!(fmt, "Permission to use the accessibility API has not been granted"),
+			Error::HelperProcessFailed(reason) => write!(fmt, "Failed to execute the command: {}", reason),
+			Error::Decode(reason) => write!(fmt, "Failed to decode a window property: {}", reason),
+			Error::BackendUnavailable(backend) => write!(fmt, "The {:?} backend is not available in this build", backend),
+		}
+	}
+}
+
+impl error::Error for Error {}
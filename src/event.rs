@@ -0,0 +1,184 @@
+use std::{collections::{BTreeMap, VecDeque}, thread, time::Duration};
+
+use crate::{ConnectionTrait, Error, Result, WindowInfo};
+
+/// How often backends without native change notifications re-query the window list.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A change to the set of windows, as yielded by [`ConnectionTrait::events`](crate::ConnectionTrait::events).
+///
+/// Serializes to an object with the `event` in snake case and the `window` it concerns.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "event", content = "window", rename_all = "snake_case"))]
+pub enum WindowEvent {
+	Opened(WindowInfo),
+	/// Carries the last known record of the window.
+	Closed(WindowInfo),
+	TitleChanged(WindowInfo),
+	FocusChanged(Option<WindowInfo>),
+}
+
+/// A blocking stream of window events, it only ends once the backend fails.
+pub type Events<'a> = Box<dyn Iterator<Item = Result<WindowEvent>> + 'a>;
+
+/// The last known windows and focus, turning fresh observations into events.
+#[derive(Default)]
+pub(crate) struct Snapshot {
+	windows: BTreeMap<u64, WindowInfo>,
+	active: Option<u64>,
+}
+
+impl Snapshot {
+	pub(crate) fn new(windows: Vec<WindowInfo>, active: Option<WindowInfo>) -> Self {
+		let mut snapshot = Self::default();
+		snapshot.windows(windows);
+		snapshot.active(active);
+		snapshot
+	}
+
+	/// Replaces the window list, reporting closed, opened and retitled windows in that order.
+	pub(crate) fn windows(&mut self, windows: Vec<WindowInfo>) -> Vec<WindowEvent> {
+		let mut previous = std::mem::take(&mut self.windows);
+		let mut opened = Vec::new();
+		let mut changed = Vec::new();
+		for window in windows {
+			match previous.remove(&window.id) {
+				None => opened.push(WindowEvent::Opened(window.clone())),
+				Some(known) if known.title != window.title => changed.push(WindowEvent::TitleChanged(window.clone())),
+				Some(_) => {},
+			}
+			self.windows.insert(window.id, window);
+		}
+		previous.into_values().map(WindowEvent::Closed).chain(opened).chain(changed).collect()
+	}
+
+	// Only X11 is notified of single title changes.
+	#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+	pub(crate) fn title(&mut self, window: WindowInfo) -> Option<WindowEvent> {
+		let known = self.windows.get_mut(&window.id)?;
+		if known.title == window.title { return None }
+		*known = window.clone();
+		Some(WindowEvent::TitleChanged(window))
+	}
+
+	pub(crate) fn active(&mut self, window: Option<WindowInfo>) -> Option<WindowEvent> {
+		let active = window.as_ref().map(|window| window.id);
+		if self.active == active { return None }
+		self.active = active;
+		Some(WindowEvent::FocusChanged(window))
+	}
+}
+
+/// Takes an unknown focus as no focus, so that windows are still reported where only the focus is unsupported.
+pub(crate) fn focus(active: Result<Option<WindowInfo>>) -> Result<Option<WindowInfo>> {
+	match active {
+		Err(Error::Unsupported(_)) => Ok(None),
+		active => active,
+	}
+}
+
+/// Event stream for backends without change notifications, diffing snapshots taken every `interval`.
+pub(crate) struct Poll<'a, C> {
+	connection: &'a C,
+	interval: Duration,
+	snapshot: Snapshot,
+	pending: VecDeque<WindowEvent>,
+}
+
+impl<'a, C: ConnectionTrait> Poll<'a, C> {
+	pub(crate) fn new(connection: &'a C, interval: Duration) -> Result<Self> {
+		let snapshot = Snapshot::new(connection.windows()?, focus(connection.active_window())?);
+		Ok(Self { connection, interval, snapshot, pending: VecDeque::new() })
+	}
+
+	fn poll(&mut self) -> Result<()> {
+		let windows = self.connection.windows()?;
+		let active = focus(self.connection.active_window())?;
+		self.pending.extend(self.snapshot.windows(windows));
+		self.pending.extend(self.snapshot.active(active));
+		Ok(())
+	}
+}
+
+impl<C: ConnectionTrait> Iterator for Poll<'_, C> {
+	type Item = Result<WindowEvent>;
+	fn next(&mut self) -> Option<Self::Item> {
+		loop {
+			if let Some(event) = self.pending.pop_front() {
+				return Some(Ok(event));
+			}
+			thread::sleep(self.interval);
+			if let Err(error) = self.poll() {
+				return Some(Err(error));
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{mock::window, MockConnection};
+
+	#[test]
+	fn test_snapshot_windows() {
+		let mut snapshot = Snapshot::new(vec![window(1, "a"), window(2, "b")], None);
+		assert_eq!(snapshot.windows(vec![window(2, "c"), window(3, "d")]), vec![
+			WindowEvent::Closed(window(1, "a")),
+			WindowEvent::Opened(window(3, "d")),
+			WindowEvent::TitleChanged(window(2, "c")),
+		]);
+		assert_eq!(snapshot.title(window(3, "d")), None);
+		assert_eq!(snapshot.title(window(3, "e")), Some(WindowEvent::TitleChanged(window(3, "e"))));
+		assert_eq!(snapshot.title(window(4, "f")), None);
+	}
+
+	#[test]
+	fn test_snapshot_active() {
+		let mut snapshot = Snapshot::new(Vec::new(), Some(window(1, "a")));
+		assert_eq!(snapshot.active(Some(window(1, "b"))), None);
+		assert_eq!(snapshot.active(None), Some(WindowEvent::FocusChanged(None)));
+		assert_eq!(snapshot.active(Some(window(2, "c"))), Some(WindowEvent::FocusChanged(Some(window(2, "c")))));
+	}
+
+	#[test]
+	fn test_focus() {
+		assert_eq!(focus(Err(Error::Unsupported("the focus"))), Ok(None));
+		assert_eq!(focus(Err(Error::PermissionDenied)), Err(Error::PermissionDenied));
+		assert_eq!(focus(Ok(Some(window(1, "a")))), Ok(Some(window(1, "a"))));
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn test_serde() {
+		let events = [
+			WindowEvent::Opened(window(1, "a")),
+			WindowEvent::Closed(window(1, "a")),
+			WindowEvent::TitleChanged(window(1, "b")),
+			WindowEvent::FocusChanged(Some(window(1, "b"))),
+			WindowEvent::FocusChanged(None),
+		];
+		for event in events {
+			let json = serde_json::to_string(&event).unwrap();
+			assert_eq!(serde_json::from_str::<WindowEvent>(&json).unwrap(), event);
+		}
+		assert_eq!(serde_json::to_string(&WindowEvent::FocusChanged(None)).unwrap(), r#"{"event":"focus_changed","window":null}"#);
+		let json = serde_json::to_value(WindowEvent::Opened(window(1, "a"))).unwrap();
+		assert_eq!(json["event"], "opened");
+		assert_eq!(json["window"]["title"], "a");
+	}
+
+	#[test]
+	fn test_poll() {
+		let connection = MockConnection::with_windows(vec![window(1, "a")]);
+		let mut events = Poll::new(&connection, Duration::ZERO).unwrap();
+		connection.set_windows(vec![window(1, "b"), window(2, "c")]);
+		connection.set_active(Some(2));
+		assert_eq!(events.next(), Some(Ok(WindowEvent::Opened(window(2, "c")))));
+		assert_eq!(events.next(), Some(Ok(WindowEvent::TitleChanged(window(1, "b")))));
+		assert_eq!(events.next(), Some(Ok(WindowEvent::FocusChanged(Some(window(2, "c"))))));
+		connection.fail_with(crate::Error::PermissionDenied);
+		assert_eq!(events.next(), Some(Err(crate::Error::PermissionDenied)));
+	}
+}
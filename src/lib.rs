@@ -1,15 +1,78 @@
-use std::{error::Error, result::Result as StdResult};
+use std::result::Result as StdResult;
 
-pub use connection::Connection;
+use event::{Poll, POLL_INTERVAL};
 
-#[cfg_attr(target_os = "linux", path = "x11.rs")]
-#[cfg_attr(target_os = "windows", path = "winapi.rs")]
-#[cfg_attr(target_os = "macos", path = "apple.rs")]
+#[cfg(feature = "async")]
+pub use async_connection::{AsyncConnection, AsyncEvents};
+pub use connection::{Backend, Connection};
+pub use error::Error;
+pub use event::{Events, WindowEvent};
+#[cfg(any(test, feature = "mock"))]
+pub use mock::MockConnection;
+pub use query::WindowQuery;
+pub use window::{Desktop, Geometry, TitleSource, WindowInfo, WindowState, WindowType};
+#[cfg(target_os = "linux")]
+pub use x11::Discovery;
+
+#[cfg(target_os = "macos")]
+mod apple;
+#[cfg(any(target_os = "macos", test))]
+mod applescript;
+#[cfg(feature = "async")]
+mod async_connection;
 mod connection;
+#[cfg(target_os = "linux")]
+mod encoding;
+mod error;
+mod event;
+#[cfg(any(test, feature = "mock"))]
+mod mock;
+#[cfg(any(target_os = "macos", test))]
+mod osascript;
+#[cfg(any(test, all(target_os = "macos", feature = "core-graphics")))]
+mod quartz;
+mod query;
+#[cfg(all(target_os = "linux", feature = "wayland"))]
+mod wayland;
+mod window;
+#[cfg(target_os = "windows")]
+mod winapi;
+#[cfg(target_os = "linux")]
+mod x11;
 
-pub type Result<T> = StdResult<T, Box<dyn Error>>;
+pub type Result<T> = StdResult<T, Error>;
 
 pub trait ConnectionTrait: Sized {
 	fn new() -> Result<Self>;
-	fn window_titles(&self) -> Result<Vec<String>>;
+	/// Every top-level window in the order the platform lists them: the order of mapping in
+	/// `_NET_CLIENT_LIST` on X11, or stacking order from the bottom without a client list, the
+	/// Z order from the top on Windows, by process on macOS and as announced on Wayland. Use
+	/// [`ConnectionTrait::windows_stacked`] where the order matters.
+	fn windows(&self) -> Result<Vec<WindowInfo>>;
+	/// Every top-level window from the topmost down. On X11 each screen is stacked on its own
+	/// and listed after the previous one. Fails with [`Error::Unsupported`] on Wayland and
+	/// macOS without the `core-graphics` feature, which do not tell the stacking order.
+	fn windows_stacked(&self) -> Result<Vec<WindowInfo>> {
+		Err(Error::Unsupported("the stacking order"))
+	}
+	/// The window that currently has the input focus, if any.
+	fn active_window(&self) -> Result<Option<WindowInfo>>;
+	/// The windows meeting every criterion of `query`, see [`WindowQuery::run`].
+	fn query(&self, query: &WindowQuery) -> Result<Vec<WindowInfo>> {
+		Ok(self.windows()?.into_iter().filter(|window| query.matches(window)).collect())
+	}
+	/// The virtual desktops in order, none if the backend has no notion of them.
+	fn desktops(&self) -> Result<Vec<Desktop>> {
+		Ok(Vec::new())
+	}
+	/// The titles of [`ConnectionTrait::windows`], in the same order. The Windows backend
+	/// leaves out hidden windows, which are mostly IME and message windows there.
+	fn window_titles(&self) -> Result<Vec<String>> {
+		Ok(self.windows()?.into_iter().map(|window| window.title).collect())
+	}
+	/// Subscribes to windows opening, closing, changing title and gaining focus.
+	/// Backends without change notifications diff the window list periodically.
+	fn events(&self) -> Result<Events<'_>> {
+		Ok(Box::new(Poll::new(self, POLL_INTERVAL)?))
+	}
 }
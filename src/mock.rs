@@ -0,0 +1,146 @@
+use std::{collections::VecDeque, sync::{Mutex, MutexGuard}};
+
+use crate::{ConnectionTrait, Error, Events, Result, WindowEvent, WindowInfo};
+
+/// A scripted backend for testing code built on [`ConnectionTrait`] without a display.
+///
+/// The windows, focus and pending events can be changed at any time through a
+/// shared reference. Unlike the real backends, the event stream ends once every
+/// scripted event has been yielded.
+#[derive(Default)]
+pub struct MockConnection {
+	state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+	windows: Vec<WindowInfo>,
+	active: Option<u64>,
+	events: VecDeque<WindowEvent>,
+	error: Option<Error>,
+}
+
+impl MockConnection {
+	pub fn with_windows(windows: Vec<WindowInfo>) -> Self {
+		let connection = Self::default();
+		connection.set_windows(windows);
+		connection
+	}
+
+	pub fn set_windows(&self, windows: Vec<WindowInfo>) {
+		self.state().windows = windows;
+	}
+
+	/// Focuses the window with the given id, it has to be part of the window list to be reported.
+	pub fn set_active(&self, id: Option<u64>) {
+		self.state().active = id;
+	}
+
+	/// Queues an event, which is applied to the window list once the event stream yields it.
+	pub fn push_event(&self, event: WindowEvent) {
+		self.state().events.push_back(event);
+	}
+
+	/// Makes the next query fail with `error`.
+	pub fn fail_with(&self, error: Error) {
+		self.state().error = Some(error);
+	}
+
+	fn state(&self) -> MutexGuard<'_, State> {
+		self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+	}
+
+	fn query(&self) -> Result<MutexGuard<'_, State>> {
+		let mut state = self.state();
+		match state.error.take() {
+			Some(error) => Err(error),
+			None => Ok(state),
+		}
+	}
+
+	/// Yields the next scripted event, `None` once there are no more.
+	pub(crate) fn next_event(&self) -> Option<Result<WindowEvent>> {
+		let mut state = match self.query() {
+			Ok(state) => state,
+			Err(error) => return Some(Err(error)),
+		};
+		let event = state.events.pop_front()?;
+		state.apply(&event);
+		Some(Ok(event))
+	}
+
+	/// Fails with the scripted error, if any, as subscribing to events does.
+	pub(crate) fn check(&self) -> Result<()> {
+		self.query().map(drop)
+	}
+}
+
+impl ConnectionTrait for MockConnection {
+	fn new() -> Result<Self> { Ok(Self::default()) }
+	fn windows(&self) -> Result<Vec<WindowInfo>> {
+		Ok(self.query()?.windows.clone())
+	}
+	/// The window list is taken to be in stacking order already.
+	fn windows_stacked(&self) -> Result<Vec<WindowInfo>> {
+		self.windows()
+	}
+	fn active_window(&self) -> Result<Option<WindowInfo>> {
+		let state = self.query()?;
+		Ok(state.windows.iter().find(|window| Some(window.id) == state.active).cloned())
+	}
+	fn events(&self) -> Result<Events<'_>> {
+		self.check()?;
+		Ok(Box::new(std::iter::from_fn(move || self.next_event())))
+	}
+}
+
+impl State {
+	fn apply(&mut self, event: &WindowEvent) {
+		match event {
+			WindowEvent::Opened(window) => self.windows.push(window.clone()),
+			WindowEvent::Closed(window) => self.windows.retain(|known| known.id != window.id),
+			WindowEvent::TitleChanged(window) => {
+				if let Some(known) = self.windows.iter_mut().find(|known| known.id == window.id) {
+					*known = window.clone();
+				}
+			},
+			WindowEvent::FocusChanged(window) => self.active = window.as_ref().map(|window| window.id),
+		}
+	}
+}
+
+/// A visible window with just an id and title, the fixture of the tests throughout the crate.
+#[cfg(test)]
+pub(crate) fn window(id: u64, title: &str) -> WindowInfo {
+	WindowInfo { id, title: title.into(), visible: true, ..WindowInfo::default() }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_queries() {
+		let connection = MockConnection::with_windows(vec![window(1, "a"), window(2, "b")]);
+		assert_eq!(connection.window_titles(), Ok(vec!["a".to_string(), "b".to_string()]));
+		assert_eq!(connection.windows_stacked(), connection.windows());
+		assert_eq!(connection.active_window(), Ok(None));
+		connection.set_active(Some(2));
+		assert_eq!(connection.active_window(), Ok(Some(window(2, "b"))));
+		connection.fail_with(Error::PermissionDenied);
+		assert_eq!(connection.windows(), Err(Error::PermissionDenied));
+		assert_eq!(connection.windows().map(|windows| windows.len()), Ok(2));
+	}
+
+	#[test]
+	fn test_events() {
+		let connection = MockConnection::with_windows(vec![window(1, "a")]);
+		connection.push_event(WindowEvent::Opened(window(2, "b")));
+		connection.push_event(WindowEvent::TitleChanged(window(1, "c")));
+		connection.push_event(WindowEvent::FocusChanged(Some(window(2, "b"))));
+		connection.push_event(WindowEvent::Closed(window(1, "c")));
+		assert_eq!(connection.events().unwrap().count(), 4);
+		assert_eq!(connection.window_titles(), Ok(vec!["b".to_string()]));
+		assert_eq!(connection.active_window(), Ok(Some(window(2, "b"))));
+	}
+}
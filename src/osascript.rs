@@ -0,0 +1,189 @@
+//! Turning the output of the `osascript` query of the macOS backend into window records.
+//!
+//! Kept apart from running the query, so that the mapping can be tested on any platform.
+
+use std::process::Output;
+
+use crate::applescript::{parse, Value};
+use crate::{Error, Geometry, Result, TitleSource, WindowInfo, WindowState, WindowType};
+
+const PREFIX: &str = r#"tell application "System Events""#;
+const PROPERTIES: &str = r#"get {name, unix id, visible, title of every window, position of every window, size of every window, value of attribute "AXMinimized" of every window} of"#;
+pub(crate) const EVERY_PROCESS: &str = "every process";
+pub(crate) const FRONTMOST_PROCESS: &str = "first process whose frontmost is true";
+const PERMISSION_ERROR: &str = "osascript is not allowed assistive access";
+
+pub(crate) fn arguments(processes: &str) -> [String; 3] {
+	["-ss".into(), "-e".into(), format!("{} to {} {}", PREFIX, PROPERTIES, processes)]
+}
+
+pub(crate) fn output(command: std::io::Result<Output>) -> Result<Value> {
+	let command = match command {
+		Ok(command_output) => command_output,
+		Err(error) => return Err(Error::HelperProcessFailed(error.to_string())),
+	};
+
+	let error = String::from_utf8_lossy(&command.stderr);
+	match (error.contains(PERMISSION_ERROR), command.status.success()) {
+		(true, _) => Err(Error::PermissionDenied),
+		(false, false) => Err(Error::HelperProcessFailed(error.trim().to_string())),
+		(false, true) => parse(&String::from_utf8_lossy(&command.stdout))
+			.map_err(|error| Error::Decode(format!("osascript output: {}", error))),
+	}
+}
+
+/// Maps the `{names, pids, visibilities, titles, positions, sizes, minimized}` lists of the query onto window records.
+pub(crate) fn windows(output: &Value) -> Result<Vec<WindowInfo>> {
+	let (names, pids, visible, titles, positions, sizes, minimized) = match output {
+		Value::List(lists) if lists.iter().all(|list| matches!(list, Value::List(_))) => match &lists[..] {
+			[names, pids, visible, titles, positions, sizes, minimized] => (names.list(), pids.list(), visible.list(), titles.list(), positions.list(), sizes.list(), minimized.list()),
+			_ => return Err(shape_error("seven lists")),
+		},
+		_ => return Err(shape_error("seven lists")),
+	};
+	let mut windows = Vec::new();
+	for (process, process_titles) in titles.iter().enumerate() {
+		let pid = match pids.get(process) {
+			Some(Value::Literal(pid)) => pid.parse::<u32>().ok(),
+			_ => None,
+		};
+		let class = match names.get(process) {
+			Some(Value::String(name)) => Some(name.clone()),
+			_ => None,
+		};
+		let visible = visible.get(process) == Some(&Value::Literal("true".into()));
+		let positions = positions.get(process).map_or(&[][..], Value::list);
+		let sizes = sizes.get(process).map_or(&[][..], Value::list);
+		let minimized = minimized.get(process).map_or(&[][..], Value::list);
+		for (index, title) in process_titles.list().iter().enumerate() {
+			if let Value::String(title) = title {
+				let geometry = match (pair(positions.get(index)), pair(sizes.get(index))) {
+					(Some((x, y)), Some((width, height))) => Some(Geometry { x, y, width: width.max(0) as u32, height: height.max(0) as u32 }),
+					_ => None,
+				};
+				let mut state = WindowState::empty();
+				state.set(WindowState::MINIMIZED, minimized.get(index) == Some(&Value::Literal("true".into())));
+				windows.push(WindowInfo {
+					id: u64::from(pid.unwrap_or(0)) << 32 | index as u64,
+					title: title.clone(),
+					title_source: TitleSource::Native,
+					pid,
+					class: class.clone(),
+					instance: None,
+					visible,
+					state,
+					window_type: WindowType::Normal,
+					geometry,
+					screen: None,
+					desktop: None,
+				});
+			}
+		}
+	}
+	Ok(windows)
+}
+
+/// An `{x, y}` position or `{width, height}` size.
+fn pair(value: Option<&Value>) -> Option<(i32, i32)> {
+	match value?.list() {
+		[Value::Literal(first), Value::Literal(second)] => Some((first.parse().ok()?, second.parse().ok()?)),
+		_ => None,
+	}
+}
+
+/// Maps the `{name, pid, visibility, titles, positions, sizes, minimized}` of the frontmost process onto its
+/// first, and thereby frontmost, window.
+pub(crate) fn active_window(output: Value) -> Result<Option<WindowInfo>> {
+	let properties = match output {
+		Value::List(properties) if properties.len() == 7 => properties.into_iter().map(|property| Value::List(vec![property])).collect(),
+		_ => return Err(shape_error("the seven properties of a process")),
+	};
+	Ok(windows(&Value::List(properties))?.into_iter().next())
+}
+
+/// Output that parses but does not have the shape the query asks for, as if `System Events` changed.
+fn shape_error(expected: &str) -> Error {
+	Error::Decode(format!("osascript output is not {}", expected))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn split(string: &str) -> Vec<String> {
+		fn strings(value: Value, titles: &mut Vec<String>) {
+			match value {
+				Value::List(values) => values.into_iter().for_each(|value| strings(value, titles)),
+				Value::Record(entries) => entries.into_iter().for_each(|(_, value)| strings(value, titles)),
+				Value::String(title) => titles.push(title),
+				Value::Literal(_) => {},
+			}
+		}
+		let mut titles = Vec::new();
+		strings(parse(string).unwrap(), &mut titles);
+		titles
+	}
+
+	#[test]
+	fn test_split() {
+		let string = r#"{{}, {"0"}, {"1", "2"}}"#;
+		assert_eq!(split(string), &["0", "1", "2"]);
+	}
+
+	#[test]
+	fn test_split_handles_no_end_quote() {
+		let input = r#"{"\" - Brave", "1", "2"}"#;
+		assert_eq!(split(input), vec![r#"" - Brave"#, "1", "2"]);
+	}
+
+	#[test]
+	fn test_split_handles_trailing_backslash() {
+		let input = r#"{{"C:\\", "next"}, {"tab\tline\n"}}"#;
+		assert_eq!(split(input), vec!["C:\\", "next", "tab\tline\n"]);
+		assert!(output(Ok(Output { status: Default::default(), stdout: br#"{"a"#.to_vec(), stderr: Vec::new() })).is_err());
+	}
+
+	#[test]
+	fn emoji_test(){
+		let input = r#"{{"👋"}, {"😾"}, {"🤮", "🎃"}}"#;
+		assert_eq!(split(input), vec![r#"👋"#, r#"😾"#, r#"🤮"#, r#"🎃"#]);
+	}
+
+	#[test]
+	fn test_arguments() {
+		let [_, _, script] = arguments(FRONTMOST_PROCESS);
+		assert!(script.starts_with(PREFIX));
+		assert!(script.ends_with("of first process whose frontmost is true"));
+		assert!(arguments(EVERY_PROCESS)[2].ends_with("} of every process"));
+	}
+
+	#[test]
+	fn test_windows() {
+		let input = r#"{{"Finder", "Dock"}, {301, 302}, {true, false}, {{"Home", missing value}, {}}, {{{-1200, 25}, {0, 0}}, {}}, {{{920, 436}, {1, 1}}, {}}, {{true, false}, {}}}"#;
+		assert_eq!(windows(&parse(input).unwrap()), Ok(vec![WindowInfo {
+			id: 301 << 32,
+			title: "Home".into(),
+			title_source: TitleSource::Native,
+			pid: Some(301),
+			class: Some("Finder".into()),
+			instance: None,
+			visible: true,
+			state: WindowState::MINIMIZED,
+			window_type: WindowType::Normal,
+			geometry: Some(Geometry { x: -1200, y: 25, width: 920, height: 436 }),
+			screen: None,
+			desktop: None,
+		}]));
+		assert!(matches!(windows(&parse(r#"{{"Finder"}, {301}}"#).unwrap()), Err(Error::Decode(_))));
+		assert!(matches!(windows(&parse(r#"{{}, {}, {}, {}, {}, {}, true}"#).unwrap()), Err(Error::Decode(_))));
+		assert!(matches!(windows(&parse(r#""Finder""#).unwrap()), Err(Error::Decode(_))));
+	}
+
+	#[test]
+	fn test_active_window() {
+		let input = r#"{"Finder", 301, true, {"Home", "Downloads"}, {{0, 25}, {40, 65}}, {{800, 600}, {800, 600}}, {false, false}}"#;
+		assert_eq!(active_window(parse(input).unwrap()).map(|window| window.map(|window| window.title)), Ok(Some("Home".into())));
+		assert_eq!(active_window(parse(r#"{"Dock", 302, true, {}, {}, {}, {}}"#).unwrap()), Ok(None));
+		assert!(matches!(active_window(parse(r#"{"Dock", 302}"#).unwrap()), Err(Error::Decode(_))));
+	}
+}
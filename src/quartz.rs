@@ -0,0 +1,233 @@
+//! Window listing through CoreGraphics' `CGWindowListCopyWindowInfo`, used by the
+//! macOS backend when the `core-graphics` feature is enabled.
+//!
+//! The window list is first converted into plain [`Dictionary`] values, so that
+//! mapping them onto window records can be tested on any platform.
+
+use std::collections::BTreeMap;
+
+use crate::{Geometry, TitleSource, WindowInfo, WindowState, WindowType};
+
+/// A window description as returned by CoreGraphics, keyed by the `kCGWindow*` names.
+pub(crate) type Dictionary = BTreeMap<String, Value>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+	Boolean(bool),
+	Number(f64),
+	String(String),
+	Dictionary(Dictionary),
+}
+
+/// Application windows live in layer 0, the menu bar, dock and overlays above it.
+const NORMAL_LAYER: f64 = 0.0;
+
+/// Every named application window, or `None` if the names are withheld. CoreGraphics only hands
+/// out the names of windows of other processes once the screen recording permission has been
+/// granted, those of our own process are named either way and tell nothing, so callers should
+/// fall back to another backend if no window of another process is named.
+pub(crate) fn windows(list: &[Dictionary]) -> Option<Vec<WindowInfo>> {
+	let windows: Vec<_> = list.iter().filter(|window| is_application_window(window)).collect();
+	let own = f64::from(std::process::id());
+	let others: Vec<_> = windows.iter().filter(|window| number(window, "kCGWindowOwnerPID") != Some(own)).collect();
+	if !others.is_empty() && others.iter().all(|window| window_info(window).is_none()) {
+		return None;
+	}
+	Some(windows.iter().filter_map(|window| window_info(window)).collect())
+}
+
+/// The first named application window of an on-screen list, which CoreGraphics orders front to back.
+pub(crate) fn active_window(list: &[Dictionary]) -> Option<WindowInfo> {
+	list.iter()
+		.filter(|window| is_application_window(window))
+		.find_map(window_info)
+}
+
+fn is_application_window(window: &Dictionary) -> bool {
+	number(window, "kCGWindowLayer") == Some(NORMAL_LAYER)
+}
+
+fn window_info(window: &Dictionary) -> Option<WindowInfo> {
+	let title = match window.get("kCGWindowName") {
+		Some(Value::String(title)) if !title.is_empty() => title.clone(),
+		_ => return None,
+	};
+	let class = match window.get("kCGWindowOwnerName") {
+		Some(Value::String(name)) => Some(name.clone()),
+		_ => None,
+	};
+	let geometry = match window.get("kCGWindowBounds") {
+		Some(Value::Dictionary(bounds)) => geometry(bounds),
+		_ => None,
+	};
+	Some(WindowInfo {
+		id: number(window, "kCGWindowNumber")? as u64,
+		title,
+		title_source: TitleSource::Native,
+		pid: number(window, "kCGWindowOwnerPID").map(|pid| pid as u32),
+		class,
+		instance: None,
+		visible: window.get("kCGWindowIsOnscreen") == Some(&Value::Boolean(true)),
+		state: WindowState::empty(),
+		window_type: WindowType::Normal,
+		geometry,
+		screen: None,
+		desktop: None,
+	})
+}
+
+/// The bounds of a window, `None` unless all four of them are given.
+fn geometry(bounds: &Dictionary) -> Option<Geometry> {
+	Some(Geometry {
+		x: number(bounds, "X")? as i32,
+		y: number(bounds, "Y")? as i32,
+		width: number(bounds, "Width")? as u32,
+		height: number(bounds, "Height")? as u32,
+	})
+}
+
+fn number(dictionary: &Dictionary, key: &str) -> Option<f64> {
+	match dictionary.get(key) {
+		Some(Value::Number(number)) => Some(*number),
+		_ => None,
+	}
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) use self::ffi::window_list;
+
+#[cfg(target_os = "macos")]
+mod ffi {
+	use core_foundation::{
+		base::{CFType, CFTypeRef, TCFType},
+		boolean::CFBoolean,
+		dictionary::CFDictionary,
+		number::CFNumber,
+		string::CFString,
+	};
+	use core_graphics::window::{copy_window_info, kCGNullWindowID, CGWindowListOption};
+
+	use super::{Dictionary, Value};
+
+	/// Copies the window list for `options`, `None` if CoreGraphics has no window server to ask.
+	pub(crate) fn window_list(options: CGWindowListOption) -> Option<Vec<Dictionary>> {
+		let list = copy_window_info(options, kCGNullWindowID)?;
+		let windows = list.iter()
+			.filter_map(|window| cf_type(*window).downcast::<CFDictionary>())
+			.map(|window| dictionary(&window))
+			.collect();
+		Some(windows)
+	}
+
+	fn dictionary(dictionary: &CFDictionary) -> Dictionary {
+		let (keys, values) = dictionary.get_keys_and_values();
+		keys.into_iter().zip(values)
+			.filter_map(|(key, value)| Some((cf_type(key).downcast::<CFString>()?.to_string(), self::value(cf_type(value))?)))
+			.collect()
+	}
+
+	fn value(value: CFType) -> Option<Value> {
+		if let Some(string) = value.downcast::<CFString>() {
+			Some(Value::String(string.to_string()))
+		} else if let Some(boolean) = value.downcast::<CFBoolean>() {
+			Some(Value::Boolean(boolean.into()))
+		} else if let Some(number) = value.downcast::<CFNumber>() {
+			number.to_f64().map(Value::Number)
+		} else {
+			value.downcast::<CFDictionary>().map(|nested| Value::Dictionary(dictionary(&nested)))
+		}
+	}
+
+	fn cf_type(reference: CFTypeRef) -> CFType {
+		// The list and its dictionaries own their entries, so they are retained rather than adopted.
+		unsafe { CFType::wrap_under_get_rule(reference) }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn dictionary(entries: Vec<(&str, Value)>) -> Dictionary {
+		entries.into_iter().map(|(key, value)| (key.to_string(), value)).collect()
+	}
+
+	/// An excerpt of `CGWindowListCopyWindowInfo` with the menu bar, a Finder window and an unnamed helper window.
+	fn recorded() -> Vec<Dictionary> {
+		vec![
+			dictionary(vec![
+				("kCGWindowLayer", Value::Number(24.0)),
+				("kCGWindowName", Value::String("Menubar".into())),
+				("kCGWindowNumber", Value::Number(3.0)),
+				("kCGWindowOwnerName", Value::String("Window Server".into())),
+				("kCGWindowOwnerPID", Value::Number(154.0)),
+			]),
+			dictionary(vec![
+				("kCGWindowBounds", Value::Dictionary(dictionary(vec![
+					("Height", Value::Number(436.0)),
+					("Width", Value::Number(920.0)),
+					("X", Value::Number(-1200.0)),
+					("Y", Value::Number(25.0)),
+				]))),
+				("kCGWindowIsOnscreen", Value::Boolean(true)),
+				("kCGWindowLayer", Value::Number(0.0)),
+				("kCGWindowName", Value::String("Downloads".into())),
+				("kCGWindowNumber", Value::Number(8121.0)),
+				("kCGWindowOwnerName", Value::String("Finder".into())),
+				("kCGWindowOwnerPID", Value::Number(612.0)),
+			]),
+			dictionary(vec![
+				("kCGWindowLayer", Value::Number(0.0)),
+				("kCGWindowNumber", Value::Number(8122.0)),
+				("kCGWindowOwnerName", Value::String("Finder".into())),
+				("kCGWindowOwnerPID", Value::Number(612.0)),
+			]),
+		]
+	}
+
+	#[test]
+	fn test_windows() {
+		let finder = WindowInfo {
+			id: 8121,
+			title: "Downloads".into(),
+			title_source: TitleSource::Native,
+			pid: Some(612),
+			class: Some("Finder".into()),
+			instance: None,
+			visible: true,
+			state: WindowState::empty(),
+			window_type: WindowType::Normal,
+			geometry: Some(Geometry { x: -1200, y: 25, width: 920, height: 436 }),
+			screen: None,
+			desktop: None,
+		};
+		assert_eq!(windows(&recorded()), Some(vec![finder.clone()]));
+		assert_eq!(active_window(&recorded()), Some(finder));
+	}
+
+	#[test]
+	fn test_windows_without_names() {
+		let mut list = recorded();
+		list[1].remove("kCGWindowName");
+		assert_eq!(windows(&list), None);
+		assert_eq!(windows(&[]), Some(Vec::new()));
+		// Our own windows are named even without the permission.
+		list.push(dictionary(vec![
+			("kCGWindowLayer", Value::Number(0.0)),
+			("kCGWindowName", Value::String("Preferences".into())),
+			("kCGWindowNumber", Value::Number(8123.0)),
+			("kCGWindowOwnerPID", Value::Number(f64::from(std::process::id()))),
+		]));
+		assert_eq!(windows(&list), None);
+	}
+
+	#[test]
+	fn test_incomplete_bounds() {
+		let mut list = recorded();
+		if let Some(Value::Dictionary(bounds)) = list[1].get_mut("kCGWindowBounds") {
+			bounds.remove("Height");
+		}
+		let windows = windows(&list).unwrap();
+		assert_eq!(windows.iter().map(|window| (window.id, window.geometry)).collect::<Vec<_>>(), [(8121, None)]);
+	}
+}
@@ -0,0 +1,161 @@
+#[cfg(feature = "regex")]
+use regex::Regex;
+
+use crate::{ConnectionTrait, Result, WindowInfo};
+
+/// Selects windows by title, class, process, visibility, taskbar presence and desktop.
+///
+/// Criteria combine, a window has to meet all of them. Backends check what they can
+/// before fetching titles, the X11 backend only describes the windows that pass.
+///
+/// ```no_run
+/// use window_titles::{Connection, ConnectionTrait, WindowQuery};
+///
+/// let connection = Connection::new()?;
+/// let terminals = WindowQuery::new().class("XTerm").visible_only().run(&connection)?;
+/// # Ok::<(), window_titles::Error>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct WindowQuery {
+	title: Option<TitleFilter>,
+	class: Option<String>,
+	pid: Option<u32>,
+	visible_only: bool,
+	taskbar_only: bool,
+	exclude_own_process: bool,
+	desktop: Option<u32>,
+}
+
+#[derive(Clone, Debug)]
+enum TitleFilter {
+	Contains(String),
+	#[cfg(feature = "regex")]
+	Matches(Regex),
+}
+
+impl WindowQuery {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Windows whose title contains `text`, case sensitively.
+	pub fn title_contains(mut self, text: impl Into<String>) -> Self {
+		self.title = Some(TitleFilter::Contains(text.into()));
+		self
+	}
+
+	/// Windows whose title matches `regex`, with the `regex` feature.
+	#[cfg(feature = "regex")]
+	pub fn title_matches(mut self, regex: Regex) -> Self {
+		self.title = Some(TitleFilter::Matches(regex));
+		self
+	}
+
+	/// Windows whose [`WindowInfo::class`] is exactly `class`.
+	pub fn class(mut self, class: impl Into<String>) -> Self {
+		self.class = Some(class.into());
+		self
+	}
+
+	/// Windows owned by the process `pid`.
+	pub fn pid(mut self, pid: u32) -> Self {
+		self.pid = Some(pid);
+		self
+	}
+
+	pub fn visible_only(mut self) -> Self {
+		self.visible_only = true;
+		self
+	}
+
+	/// Only the windows a task switcher would offer, see [`WindowInfo::is_taskbar_window`].
+	pub fn taskbar_only(mut self) -> Self {
+		self.taskbar_only = true;
+		self
+	}
+
+	/// Leaves out the windows of the calling process.
+	pub fn exclude_own_process(mut self) -> Self {
+		self.exclude_own_process = true;
+		self
+	}
+
+	/// Windows on the virtual desktop with the given index, including those shown on every desktop.
+	pub fn desktop(mut self, desktop: u32) -> Self {
+		self.desktop = Some(desktop);
+		self
+	}
+
+	pub fn run<C: ConnectionTrait>(&self, connection: &C) -> Result<Vec<WindowInfo>> {
+		connection.query(self)
+	}
+
+	pub fn matches(&self, window: &WindowInfo) -> bool {
+		self.matches_untitled(window) && match &self.title {
+			None => true,
+			Some(TitleFilter::Contains(text)) => window.title.contains(text.as_str()),
+			#[cfg(feature = "regex")]
+			Some(TitleFilter::Matches(regex)) => regex.is_match(&window.title),
+		}
+	}
+
+	/// Checks every criterion but the title, for backends that look at a window before fetching its title.
+	pub(crate) fn matches_untitled(&self, window: &WindowInfo) -> bool {
+		self.class.as_ref().is_none_or(|class| window.class.as_ref() == Some(class))
+			&& self.pid.is_none_or(|pid| window.pid == Some(pid))
+			&& (!self.visible_only || window.visible)
+			&& (!self.taskbar_only || window.is_taskbar_window())
+			&& (!self.exclude_own_process || window.pid != Some(std::process::id()))
+			&& self.desktop.is_none_or(|desktop| window.desktop == Some(desktop) || window.is_sticky())
+	}
+
+	/// Whether any criterion besides the title is set, making a look before fetching titles worthwhile.
+	#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+	pub(crate) fn filters_untitled(&self) -> bool {
+		self.class.is_some() || self.pid.is_some() || self.visible_only || self.taskbar_only || self.exclude_own_process || self.desktop.is_some()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{mock, MockConnection};
+
+	fn window(id: u64, title: &str, class: &str, pid: u32) -> WindowInfo {
+		WindowInfo { class: Some(class.into()), pid: Some(pid), ..mock::window(id, title) }
+	}
+
+	#[test]
+	fn test_matches() {
+		let mut editor = window(1, "notes.txt - gedit", "Gedit", 10);
+		assert!(WindowQuery::new().matches(&editor));
+		assert!(WindowQuery::new().title_contains("notes").class("Gedit").pid(10).matches(&editor));
+		assert!(!WindowQuery::new().title_contains("Notes").matches(&editor));
+		assert!(!WindowQuery::new().class("gedit").matches(&editor));
+		assert!(!WindowQuery::new().desktop(1).matches(&editor));
+		editor.desktop = Some(WindowInfo::ALL_DESKTOPS);
+		assert!(WindowQuery::new().desktop(1).matches(&editor));
+		editor.visible = false;
+		assert!(!WindowQuery::new().visible_only().matches(&editor));
+		assert!(WindowQuery::new().taskbar_only().matches(&editor));
+		editor.window_type = crate::WindowType::Dock;
+		assert!(!WindowQuery::new().taskbar_only().matches(&editor));
+		editor.pid = Some(std::process::id());
+		assert!(!WindowQuery::new().exclude_own_process().matches(&editor));
+	}
+
+	#[cfg(feature = "regex")]
+	#[test]
+	fn test_title_matches() {
+		let query = WindowQuery::new().title_matches(Regex::new(r"\.txt - \w+$").unwrap());
+		assert!(query.matches(&window(1, "notes.txt - gedit", "Gedit", 10)));
+		assert!(!query.matches(&window(2, "notes.md - gedit", "Gedit", 10)));
+	}
+
+	#[test]
+	fn test_run() {
+		let connection = MockConnection::with_windows(vec![window(1, "a", "XTerm", 10), window(2, "b", "Gedit", 11)]);
+		let windows = WindowQuery::new().class("Gedit").run(&connection).unwrap();
+		assert_eq!(windows, vec![window(2, "b", "Gedit", 11)]);
+	}
+}
@@ -0,0 +1,388 @@
+//! Native Wayland windows through the foreign toplevel protocols. Compositors based on
+//! wlroots offer `zwlr_foreign_toplevel_manager_v1`, which also tells the focused,
+//! minimized, maximized and fullscreen toplevels; `ext_foreign_toplevel_list_v1` only
+//! lists titles and app ids.
+
+use std::sync::{Mutex, MutexGuard};
+
+use wayland_client::{
+	backend::ObjectId,
+	event_created_child,
+	globals::{registry_queue_init, GlobalList, GlobalListContents},
+	protocol::wl_registry::WlRegistry,
+	Connection as WaylandConnection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum,
+};
+use wayland_protocols::ext::foreign_toplevel_list::v1::client::{
+	ext_foreign_toplevel_handle_v1::{self, ExtForeignToplevelHandleV1},
+	ext_foreign_toplevel_list_v1::{self, ExtForeignToplevelListV1},
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+	zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+	zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+
+use crate::{ConnectionTrait, Error, Result, TitleSource, WindowInfo, WindowState};
+
+pub struct Connection {
+	queue: Mutex<Queue>,
+	protocol: Protocol,
+}
+
+struct Queue {
+	events: EventQueue<State>,
+	state: State,
+}
+
+/// The bound toplevel list, kept alive for as long as the connection.
+enum Protocol {
+	Wlr(#[allow(dead_code)] ZwlrForeignToplevelManagerV1),
+	Ext(#[allow(dead_code)] ExtForeignToplevelListV1),
+}
+
+/// The toplevels announced so far, in the order the compositor created them.
+#[derive(Default)]
+struct State {
+	toplevels: Vec<Toplevel>,
+}
+
+struct Toplevel {
+	id: ObjectId,
+	/// The identifier `ext_foreign_toplevel_list_v1` assigns, unique for the lifetime of the compositor.
+	identifier: Option<String>,
+	pending: Properties,
+	/// The properties as of the last `done` event, toplevels without one are not reported yet.
+	current: Option<Properties>,
+}
+
+#[derive(Clone, Default)]
+struct Properties {
+	title: String,
+	app_id: Option<String>,
+	activated: bool,
+	state: WindowState,
+}
+
+impl Connection {
+	/// Uses an established connection to a compositor, such as one end of a socket pair in tests.
+	pub(crate) fn from_connection(connection: WaylandConnection) -> Result<Self> {
+		let (globals, mut events) = registry_queue_init::<State>(&connection)
+			.map_err(|error| Error::ConnectionFailed(error.to_string()))?;
+		let protocol = bind(&globals, &events.handle())?;
+		let mut state = State::default();
+		events.roundtrip(&mut state)?;
+		Ok(Self { queue: Mutex::new(Queue { events, state }), protocol })
+	}
+
+	/// Processes everything the compositor sent up to now.
+	fn update(&self) -> Result<MutexGuard<'_, Queue>> {
+		let mut queue = self.queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+		let Queue { events, state } = &mut *queue;
+		events.roundtrip(state)?;
+		Ok(queue)
+	}
+}
+
+impl ConnectionTrait for Connection {
+	fn new() -> Result<Self> {
+		let connection = WaylandConnection::connect_to_env().map_err(|error| Error::ConnectionFailed(error.to_string()))?;
+		Self::from_connection(connection)
+	}
+	fn windows(&self) -> Result<Vec<WindowInfo>> {
+		Ok(self.update()?.state.toplevels.iter().filter_map(Toplevel::window_info).collect())
+	}
+	/// Only `zwlr_foreign_toplevel_manager_v1` tells which toplevel is activated.
+	fn active_window(&self) -> Result<Option<WindowInfo>> {
+		if let Protocol::Ext(_) = self.protocol {
+			return Err(Error::Unsupported("the focus without zwlr_foreign_toplevel_manager_v1"));
+		}
+		let queue = self.update()?;
+		Ok(queue.state.toplevels.iter()
+			.filter(|toplevel| toplevel.current.as_ref().is_some_and(|current| current.activated))
+			.find_map(Toplevel::window_info))
+	}
+}
+
+/// Prefers the wlroots protocol for the activated, minimized and other states it reports.
+fn bind(globals: &GlobalList, queue: &QueueHandle<State>) -> Result<Protocol> {
+	if let Ok(manager) = globals.bind(queue, 1..=3, ()) {
+		return Ok(Protocol::Wlr(manager));
+	}
+	match globals.bind(queue, 1..=1, ()) {
+		Ok(list) => Ok(Protocol::Ext(list)),
+		Err(_) => Err(Error::Unsupported("toplevels without zwlr_foreign_toplevel_manager_v1 or ext_foreign_toplevel_list_v1")),
+	}
+}
+
+impl Toplevel {
+	fn window_info(&self) -> Option<WindowInfo> {
+		let current = self.current.as_ref()?;
+		// Object ids are reused once a toplevel is gone, the identifiers of the ext protocol never are.
+		let id = match &self.identifier {
+			Some(identifier) => identifier_id(identifier),
+			None => self.id.protocol_id().into(),
+		};
+		Some(WindowInfo {
+			id,
+			title: current.title.clone(),
+			title_source: TitleSource::Native,
+			class: current.app_id.clone(),
+			visible: !current.state.contains(WindowState::MINIMIZED),
+			state: current.state,
+			..WindowInfo::default()
+		})
+	}
+}
+
+impl State {
+	fn added(&mut self, id: ObjectId) {
+		self.toplevels.push(Toplevel { id, identifier: None, pending: Properties::default(), current: None });
+	}
+
+	fn identified(&mut self, id: &ObjectId, identifier: String) {
+		if let Some(toplevel) = self.toplevels.iter_mut().find(|toplevel| &toplevel.id == id) {
+			toplevel.identifier = Some(identifier);
+		}
+	}
+
+	fn pending(&mut self, id: &ObjectId) -> Option<&mut Properties> {
+		self.toplevels.iter_mut().find(|toplevel| &toplevel.id == id).map(|toplevel| &mut toplevel.pending)
+	}
+
+	fn done(&mut self, id: &ObjectId) {
+		if let Some(toplevel) = self.toplevels.iter_mut().find(|toplevel| &toplevel.id == id) {
+			toplevel.current = Some(toplevel.pending.clone());
+		}
+	}
+
+	fn closed(&mut self, id: &ObjectId) {
+		self.toplevels.retain(|toplevel| &toplevel.id != id);
+	}
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for State {
+	fn event(_: &mut Self, _: &WlRegistry, _: <WlRegistry as Proxy>::Event, _: &GlobalListContents, _: &WaylandConnection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for State {
+	fn event(state: &mut Self, _: &ZwlrForeignToplevelManagerV1, event: zwlr_foreign_toplevel_manager_v1::Event, _: &(), _: &WaylandConnection, _: &QueueHandle<Self>) {
+		if let zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } = event {
+			state.added(toplevel.id());
+		}
+	}
+
+	event_created_child!(State, ZwlrForeignToplevelManagerV1, [
+		zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+	]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for State {
+	fn event(state: &mut Self, handle: &ZwlrForeignToplevelHandleV1, event: zwlr_foreign_toplevel_handle_v1::Event, _: &(), _: &WaylandConnection, _: &QueueHandle<Self>) {
+		use zwlr_foreign_toplevel_handle_v1::{Event, State as ToplevelState};
+		let id = handle.id();
+		match event {
+			Event::Title { title } => state.pending(&id).into_iter().for_each(|pending| pending.title = title.clone()),
+			Event::AppId { app_id } => state.pending(&id).into_iter().for_each(|pending| pending.app_id = Some(app_id.clone())),
+			Event::State { state: states } => if let Some(pending) = state.pending(&id) {
+				// An array of native endian 32 bit enum values.
+				let states: Vec<_> = states.chunks_exact(4)
+					.map(|value| WEnum::<ToplevelState>::from(u32::from_ne_bytes([value[0], value[1], value[2], value[3]])))
+					.collect();
+				let has = |state| states.contains(&WEnum::Value(state));
+				pending.activated = has(ToplevelState::Activated);
+				pending.state = WindowState::empty();
+				pending.state.set(WindowState::MINIMIZED, has(ToplevelState::Minimized));
+				pending.state.set(WindowState::MAXIMIZED, has(ToplevelState::Maximized));
+				pending.state.set(WindowState::FULLSCREEN, has(ToplevelState::Fullscreen));
+			},
+			Event::Done => state.done(&id),
+			Event::Closed => {
+				state.closed(&id);
+				handle.destroy();
+			},
+			_ => {},
+		}
+	}
+}
+
+impl Dispatch<ExtForeignToplevelListV1, ()> for State {
+	fn event(state: &mut Self, _: &ExtForeignToplevelListV1, event: ext_foreign_toplevel_list_v1::Event, _: &(), _: &WaylandConnection, _: &QueueHandle<Self>) {
+		if let ext_foreign_toplevel_list_v1::Event::Toplevel { toplevel } = event {
+			state.added(toplevel.id());
+		}
+	}
+
+	event_created_child!(State, ExtForeignToplevelListV1, [
+		ext_foreign_toplevel_list_v1::EVT_TOPLEVEL_OPCODE => (ExtForeignToplevelHandleV1, ()),
+	]);
+}
+
+impl Dispatch<ExtForeignToplevelHandleV1, ()> for State {
+	fn event(state: &mut Self, handle: &ExtForeignToplevelHandleV1, event: ext_foreign_toplevel_handle_v1::Event, _: &(), _: &WaylandConnection, _: &QueueHandle<Self>) {
+		use ext_foreign_toplevel_handle_v1::Event;
+		let id = handle.id();
+		match event {
+			Event::Title { title } => state.pending(&id).into_iter().for_each(|pending| pending.title = title.clone()),
+			Event::AppId { app_id } => state.pending(&id).into_iter().for_each(|pending| pending.app_id = Some(app_id.clone())),
+			Event::Identifier { identifier } => state.identified(&id, identifier),
+			Event::Done => state.done(&id),
+			Event::Closed => {
+				state.closed(&id);
+				handle.destroy();
+			},
+			_ => {},
+		}
+	}
+}
+
+/// Hashes an ext toplevel identifier into a window id with 64 bit FNV-1a, which unlike the
+/// standard library hasher gives the same id in every process.
+fn identifier_id(identifier: &str) -> u64 {
+	identifier.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x100_0000_01b3))
+}
+
+impl From<wayland_client::DispatchError> for Error {
+	fn from(error: wayland_client::DispatchError) -> Self {
+		Error::ConnectionFailed(error.to_string())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		os::unix::net::UnixStream,
+		sync::{atomic::{AtomicBool, Ordering}, Arc},
+		thread::{self, JoinHandle},
+		time::Duration,
+	};
+
+	use wayland_protocols::ext::foreign_toplevel_list::v1::server::{
+		ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1 as ExtHandle,
+		ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1 as ExtList,
+	};
+	use wayland_protocols_wlr::foreign_toplevel::v1::server::{
+		zwlr_foreign_toplevel_handle_v1::{State as ToplevelState, ZwlrForeignToplevelHandleV1 as WlrHandle},
+		zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1 as WlrManager,
+	};
+	use wayland_server::{backend::ClientData, Client, DataInit, Display, DisplayHandle, GlobalDispatch, New, Resource};
+
+	use super::*;
+
+	/// A toplevel the test compositor announces, optionally closing it right away.
+	#[derive(Clone)]
+	struct Scripted {
+		title: &'static str,
+		app_id: &'static str,
+		states: Vec<ToplevelState>,
+		closed: bool,
+	}
+
+	struct Compositor {
+		toplevels: Vec<Scripted>,
+	}
+
+	struct Client_;
+	impl ClientData for Client_ {}
+
+	/// Runs a compositor offering the given globals on one end of a socket pair until `stop` is set.
+	fn compositor(toplevels: Vec<Scripted>, wlr: bool, ext: bool) -> (WaylandConnection, Arc<AtomicBool>, JoinHandle<()>) {
+		let (server, client) = UnixStream::pair().unwrap();
+		let stop = Arc::new(AtomicBool::new(false));
+		let stopped = stop.clone();
+		let thread = thread::spawn(move || {
+			let mut display = Display::<Compositor>::new().unwrap();
+			if wlr { display.handle().create_global::<Compositor, WlrManager, ()>(3, ()); }
+			if ext { display.handle().create_global::<Compositor, ExtList, ()>(1, ()); }
+			display.handle().insert_client(server, Arc::new(Client_)).unwrap();
+			let mut compositor = Compositor { toplevels };
+			while !stopped.load(Ordering::Relaxed) {
+				display.dispatch_clients(&mut compositor).unwrap();
+				display.flush_clients().unwrap();
+				thread::sleep(Duration::from_millis(1));
+			}
+		});
+		(WaylandConnection::from_socket(client).unwrap(), stop, thread)
+	}
+
+	impl GlobalDispatch<WlrManager, ()> for Compositor {
+		fn bind(state: &mut Self, display: &DisplayHandle, client: &Client, resource: New<WlrManager>, _: &(), init: &mut DataInit<'_, Self>) {
+			let manager = init.init(resource, ());
+			for scripted in &state.toplevels {
+				let handle = client.create_resource::<WlrHandle, (), Self>(display, manager.version(), ()).unwrap();
+				manager.toplevel(&handle);
+				handle.title(scripted.title.into());
+				handle.app_id(scripted.app_id.into());
+				handle.state(scripted.states.iter().flat_map(|&state| u32::from(state).to_ne_bytes()).collect());
+				handle.done();
+				if scripted.closed { handle.closed() }
+			}
+		}
+	}
+
+	impl GlobalDispatch<ExtList, ()> for Compositor {
+		fn bind(state: &mut Self, display: &DisplayHandle, client: &Client, resource: New<ExtList>, _: &(), init: &mut DataInit<'_, Self>) {
+			let list = init.init(resource, ());
+			for scripted in &state.toplevels {
+				let handle = client.create_resource::<ExtHandle, (), Self>(display, list.version(), ()).unwrap();
+				list.toplevel(&handle);
+				handle.identifier(format!("{}-{}", scripted.app_id, scripted.title));
+				handle.title(scripted.title.into());
+				handle.app_id(scripted.app_id.into());
+				handle.done();
+				if scripted.closed { handle.closed() }
+			}
+		}
+	}
+
+	macro_rules! ignore_requests {
+		($($interface:ty),*) => {$(
+			impl wayland_server::Dispatch<$interface, ()> for Compositor {
+				fn request(_: &mut Self, _: &Client, _: &$interface, _: <$interface as Resource>::Request, _: &(), _: &DisplayHandle, _: &mut DataInit<'_, Self>) {}
+			}
+		)*};
+	}
+	ignore_requests!(WlrManager, WlrHandle, ExtList, ExtHandle);
+
+	fn scripted() -> Vec<Scripted> {
+		vec![
+			Scripted { title: "Inbox", app_id: "thunderbird", states: vec![ToplevelState::Minimized], closed: false },
+			Scripted { title: "~", app_id: "foot", states: vec![ToplevelState::Activated, ToplevelState::Maximized], closed: false },
+			Scripted { title: "Splash", app_id: "gimp", states: Vec::new(), closed: true },
+		]
+	}
+
+	fn summary(windows: &[WindowInfo]) -> Vec<(&str, Option<&str>, WindowState)> {
+		windows.iter().map(|window| (window.title.as_str(), window.class.as_deref(), window.state)).collect()
+	}
+
+	#[test]
+	fn test_wlr_foreign_toplevels() {
+		let (client, stop, thread) = compositor(scripted(), true, true);
+		let connection = Connection::from_connection(client).unwrap();
+		assert_eq!(summary(&connection.windows().unwrap()), vec![("Inbox", Some("thunderbird"), WindowState::MINIMIZED), ("~", Some("foot"), WindowState::MAXIMIZED)]);
+		assert_eq!(connection.active_window().unwrap().map(|window| window.title), Some("~".to_string()));
+		stop.store(true, Ordering::Relaxed);
+		thread.join().unwrap();
+	}
+
+	#[test]
+	fn test_ext_foreign_toplevels() {
+		let (client, stop, thread) = compositor(scripted(), false, true);
+		let connection = Connection::from_connection(client).unwrap();
+		let windows = connection.windows().unwrap();
+		assert_eq!(summary(&windows), vec![("Inbox", Some("thunderbird"), WindowState::empty()), ("~", Some("foot"), WindowState::empty())]);
+		assert_eq!(windows.iter().map(|window| window.id).collect::<Vec<_>>(), [identifier_id("thunderbird-Inbox"), identifier_id("foot-~")]);
+		assert!(matches!(connection.active_window(), Err(Error::Unsupported(_))));
+		assert!(matches!(connection.windows_stacked(), Err(Error::Unsupported(_))));
+		assert!(connection.events().is_ok());
+		stop.store(true, Ordering::Relaxed);
+		thread.join().unwrap();
+	}
+
+	#[test]
+	fn test_without_foreign_toplevels() {
+		let (client, stop, thread) = compositor(scripted(), false, false);
+		assert!(matches!(Connection::from_connection(client), Err(Error::Unsupported(_))));
+		stop.store(true, Ordering::Relaxed);
+		thread.join().unwrap();
+	}
+}
@@ -1,40 +1,176 @@
+use std::{mem, ptr};
+
 use winapi::{
     um::{
-        winuser::{EnumWindows, GetWindowTextW, GetWindowTextLengthW, IsWindowVisible},
+        dwmapi::{DwmGetWindowAttribute, DWMWA_EXTENDED_FRAME_BOUNDS},
+        winuser::{
+            EnumDisplayMonitors, EnumWindows, GetClassNameW, GetForegroundWindow, GetMonitorInfoW, GetWindow, GetWindowLongW,
+            GetTopWindow, GetWindowRect, GetWindowTextW, GetWindowTextLengthW, GetWindowThreadProcessId, IsIconic, IsWindowVisible, IsZoomed,
+            MonitorFromWindow, GWL_EXSTYLE, GW_HWNDNEXT, GW_OWNER, MONITORINFO, MONITOR_DEFAULTTONULL, WS_EX_APPWINDOW, WS_EX_TOOLWINDOW,
+        },
         winnt::LPWSTR
     },
-    shared::{minwindef::{BOOL, LPARAM}, windef::HWND},
+    shared::{
+        minwindef::{BOOL, DWORD, LPARAM, LPVOID},
+        windef::{HDC, HMONITOR, HWND, LPRECT, RECT},
+        winerror::S_OK,
+    },
 };
 
-use crate::{ConnectionTrait, Result};
+use crate::{ConnectionTrait, Geometry, Result, TitleSource, WindowInfo, WindowState, WindowType};
+
+struct State {
+    windows: Vec<WindowInfo>,
+    monitors: Vec<HMONITOR>,
+}
 
 pub struct Connection;
 impl ConnectionTrait for Connection {
     fn new() -> Result<Self> { Ok(Self) }
-    fn window_titles(&self) -> Result<Vec<String>> {
-        let state: Box<Vec<String>> = Box::new(Vec::with_capacity(1000));
+    fn windows(&self) -> Result<Vec<WindowInfo>> {
+        let state = Box::new(State { windows: Vec::with_capacity(1000), monitors: monitors() });
         let ptr = Box::into_raw(state);
         let state;
         unsafe {
             EnumWindows(Some(enumerate_windows), ptr as LPARAM);
             state = Box::from_raw(ptr);
         }
-        Ok(*state)
+        Ok(state.windows)
+    }
+    /// Walks the Z order from the topmost window down with `GW_HWNDNEXT`.
+    fn windows_stacked(&self) -> Result<Vec<WindowInfo>> {
+        let monitors = monitors();
+        let mut windows = Vec::new();
+        let mut window = unsafe { GetTopWindow(ptr::null_mut()) };
+        while !window.is_null() {
+            windows.extend(unsafe { window_info(window, &monitors) });
+            window = unsafe { GetWindow(window, GW_HWNDNEXT) };
+        }
+        Ok(windows)
+    }
+    fn active_window(&self) -> Result<Option<WindowInfo>> {
+        let window = unsafe { GetForegroundWindow() };
+        if window.is_null() { return Ok(None) }
+        Ok(unsafe { window_info(window, &monitors()) })
+    }
+    // Hidden top-level windows with a title are plentiful on Windows (IME and
+    // message windows), so titles keep listing only the visible ones.
+    fn window_titles(&self) -> Result<Vec<String>> {
+        Ok(self.windows()?.into_iter().filter(|window| window.visible).map(|window| window.title).collect())
     }
 }
 
 unsafe extern "system" fn enumerate_windows(window: HWND, state: LPARAM) -> BOOL {
-    if IsWindowVisible(window) == 0 { return true.into() }
-    let state = state as *mut Vec<String>;
+    let state = state as *mut State;
+    if let Some(window) = window_info(window, &(*state).monitors) {
+        (*state).windows.push(window);
+    }
+    true.into()
+}
+
+/// The display monitors in the order `EnumDisplayMonitors` reports them, which is what screen indices refer to.
+fn monitors() -> Vec<HMONITOR> {
+    let mut monitors: Vec<HMONITOR> = Vec::new();
+    unsafe {
+        EnumDisplayMonitors(std::ptr::null_mut(), std::ptr::null(), Some(enumerate_monitors), &mut monitors as *mut Vec<HMONITOR> as LPARAM);
+    }
+    monitors
+}
+
+unsafe extern "system" fn enumerate_monitors(monitor: HMONITOR, _: HDC, _: LPRECT, state: LPARAM) -> BOOL {
+    (*(state as *mut Vec<HMONITOR>)).push(monitor);
+    true.into()
+}
+
+unsafe fn window_info(window: HWND, monitors: &[HMONITOR]) -> Option<WindowInfo> {
     let mut length = GetWindowTextLengthW(window);
-    if length == 0 { return true.into() }
+    if length == 0 { return None }
     length = length + 1;
     let mut title: Vec<u16> = vec![0; length as usize];
     let textw = GetWindowTextW(window, title.as_mut_ptr() as LPWSTR, length);
-    if textw != 0 {
-        if let Ok(title) = String::from_utf16(title[0..(textw as usize)].as_ref()) {
-            (*state).push(title);
-        }
+    if textw == 0 { return None }
+    let title = String::from_utf16(title[0..(textw as usize)].as_ref()).ok()?;
+    let mut pid: DWORD = 0;
+    GetWindowThreadProcessId(window, &mut pid);
+    let geometry = geometry(window);
+    let (window_type, skip_taskbar) = window_type(window);
+    let mut state = state(window, geometry);
+    state.set(WindowState::SKIP_TASKBAR, skip_taskbar || state.contains(WindowState::HIDDEN));
+    Some(WindowInfo {
+        id: window as usize as u64,
+        title,
+        title_source: TitleSource::Native,
+        pid: if pid == 0 { None } else { Some(pid) },
+        class: class_name(window),
+        instance: None,
+        visible: IsWindowVisible(window) != 0,
+        state,
+        window_type,
+        geometry,
+        screen: monitors.iter().position(|&monitor| monitor == MonitorFromWindow(window, MONITOR_DEFAULTTONULL)),
+        desktop: None,
+    })
+}
+
+/// The bounds DWM draws the window frame at, excluding the invisible resize borders
+/// `GetWindowRect` includes, which is used when composition is unavailable.
+unsafe fn geometry(window: HWND) -> Option<Geometry> {
+    let mut rect: RECT = mem::zeroed();
+    let size = mem::size_of::<RECT>() as DWORD;
+    if DwmGetWindowAttribute(window, DWMWA_EXTENDED_FRAME_BOUNDS, &mut rect as *mut RECT as LPVOID, size) != S_OK
+        && GetWindowRect(window, &mut rect) == 0 {
+        return None
     }
-    true.into()
+    Some(Geometry {
+        x: rect.left,
+        y: rect.top,
+        width: (rect.right - rect.left).max(0) as u32,
+        height: (rect.bottom - rect.top).max(0) as u32,
+    })
+}
+
+unsafe fn state(window: HWND, geometry: Option<Geometry>) -> WindowState {
+    let mut state = WindowState::empty();
+    state.set(WindowState::MINIMIZED, IsIconic(window) != 0);
+    state.set(WindowState::MAXIMIZED, IsZoomed(window) != 0);
+    state.set(WindowState::HIDDEN, IsWindowVisible(window) == 0);
+    // Windows has no fullscreen state, fullscreen windows are restored ones covering their whole monitor.
+    if state.is_empty() {
+        state.set(WindowState::FULLSCREEN, geometry.is_some_and(|geometry| covers_monitor(window, geometry)));
+    }
+    state
+}
+
+/// Tool windows are utility windows and owned windows dialogs. Both are left out of the
+/// taskbar unless they ask to be shown with `WS_EX_APPWINDOW`.
+unsafe fn window_type(window: HWND) -> (WindowType, bool) {
+    let ex_style = GetWindowLongW(window, GWL_EXSTYLE) as DWORD;
+    let window_type = if ex_style & WS_EX_TOOLWINDOW != 0 {
+        WindowType::Utility
+    } else if !GetWindow(window, GW_OWNER).is_null() {
+        WindowType::Dialog
+    } else {
+        WindowType::Normal
+    };
+    (window_type, window_type != WindowType::Normal && ex_style & WS_EX_APPWINDOW == 0)
+}
+
+unsafe fn covers_monitor(window: HWND, geometry: Geometry) -> bool {
+    let mut info: MONITORINFO = mem::zeroed();
+    info.cbSize = mem::size_of::<MONITORINFO>() as DWORD;
+    if GetMonitorInfoW(MonitorFromWindow(window, MONITOR_DEFAULTTONULL), &mut info) == 0 {
+        return false
+    }
+    let monitor = info.rcMonitor;
+    geometry.x == monitor.left && geometry.y == monitor.top
+        && geometry.width as i32 == monitor.right - monitor.left
+        && geometry.height as i32 == monitor.bottom - monitor.top
+}
+
+unsafe fn class_name(window: HWND) -> Option<String> {
+    // Window class names are limited to 256 characters.
+    let mut class: Vec<u16> = vec![0; 257];
+    let length = GetClassNameW(window, class.as_mut_ptr() as LPWSTR, class.len() as i32);
+    if length == 0 { return None }
+    String::from_utf16(&class[0..(length as usize)]).ok()
 }
@@ -0,0 +1,260 @@
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
+
+use bitflags::bitflags;
+
+/// A single top-level window as reported by the platform backend.
+///
+/// With the `serde` feature it serializes to the JSON object described in the README, fields
+/// missing when deserializing take their default.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct WindowInfo {
+	/// Backend native identifier: the XID on X11, the `HWND` on Windows, the
+	/// `CGWindowID` with the `core-graphics` macOS backend and, as `osascript`
+	/// exposes no window handle, the owning pid in the upper and the window
+	/// index in the lower 32 bits otherwise.
+	pub id: u64,
+	pub title: String,
+	pub title_source: TitleSource,
+	/// Id of the process owning the window, if the backend can tell. On X11 it is
+	/// read from `_NET_WM_PID`, or the X-Resource extension with the `xres` feature,
+	/// and may belong to another host for clients connected over the network.
+	pub pid: Option<u32>,
+	/// Identifies the application across title changes: the `WM_CLASS` class on X11,
+	/// the window class on Windows and the process name on macOS.
+	pub class: Option<String>,
+	/// The `WM_CLASS` instance on X11, usually the executable or a name given with
+	/// `-name`. The other platforms have no such distinction.
+	pub instance: Option<String>,
+	pub visible: bool,
+	pub state: WindowState,
+	pub window_type: WindowType,
+	/// Outer bounds on screen, including the frame drawn by the window manager.
+	pub geometry: Option<Geometry>,
+	/// Index of the X screen on X11 and of the monitor on Windows the window is on.
+	pub screen: Option<usize>,
+	/// Index of the virtual desktop the window is on, from `_NET_WM_DESKTOP` on X11.
+	/// [`WindowInfo::ALL_DESKTOPS`] stands for windows shown on every desktop.
+	pub desktop: Option<u32>,
+}
+
+impl WindowInfo {
+	/// The [`WindowInfo::desktop`] of windows shown on every desktop, as EWMH defines it.
+	pub const ALL_DESKTOPS: u32 = 0xFFFF_FFFF;
+
+	/// Whether the window would show up in a taskbar or task switcher: a normal window or
+	/// dialog that does not ask to be left out.
+	pub fn is_taskbar_window(&self) -> bool {
+		matches!(self.window_type, WindowType::Normal | WindowType::Dialog) && !self.state.contains(WindowState::SKIP_TASKBAR)
+	}
+
+	/// Whether the window is shown on every desktop rather than a single one.
+	pub fn is_sticky(&self) -> bool {
+		self.desktop == Some(Self::ALL_DESKTOPS)
+	}
+
+	/// Resolves the executable of the owning process through `/proc/<pid>/exe`, which
+	/// fails for processes of other users unless running privileged.
+	#[cfg(target_os = "linux")]
+	pub fn executable(&self) -> Option<PathBuf> {
+		std::fs::read_link(format!("/proc/{}/exe", self.pid?)).ok()
+	}
+}
+
+/// Position and size of a window in global screen coordinates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Geometry {
+	pub x: i32,
+	pub y: i32,
+	pub width: u32,
+	pub height: u32,
+}
+
+bitflags! {
+	/// What the window manager reports about how a window is shown.
+	///
+	/// X11 reads `_NET_WM_STATE`, `WM_STATE` and the `WM_HINTS` urgency, Windows has no
+	/// urgency and the macOS backend only tells minimized windows through `osascript`.
+	///
+	/// Serializes to a list of the set flags in snake case, such as `["minimized", "urgent"]`.
+	#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+	pub struct WindowState: u32 {
+		/// Iconified, only its taskbar entry or icon is left.
+		const MINIMIZED = 1;
+		/// Maximized in both directions.
+		const MAXIMIZED = 1 << 1;
+		const FULLSCREEN = 1 << 2;
+		/// Not shown on screen, `_NET_WM_STATE_HIDDEN` on X11 and invisible windows on Windows.
+		const HIDDEN = 1 << 3;
+		/// Demands attention, through `_NET_WM_STATE_DEMANDS_ATTENTION` or the `WM_HINTS` urgency hint.
+		const URGENT = 1 << 4;
+		/// Left out of the taskbar, through `_NET_WM_STATE_SKIP_TASKBAR` on X11 and for invisible,
+		/// owned and tool windows without `WS_EX_APPWINDOW` on Windows.
+		const SKIP_TASKBAR = 1 << 5;
+	}
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for WindowState {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_seq(self.iter_names().map(|(name, _)| name.to_lowercase()))
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for WindowState {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		Vec::<String>::deserialize(deserializer)?.iter().try_fold(WindowState::empty(), |state, name| {
+			match WindowState::from_name(&name.to_uppercase()) {
+				Some(flag) if name.chars().all(|c| c.is_ascii_lowercase() || c == '_') => Ok(state | flag),
+				_ => Err(serde::de::Error::custom(format_args!("unknown window state `{}`", name))),
+			}
+		})
+	}
+}
+
+/// What a window is for, after the EWMH `_NET_WM_WINDOW_TYPE` values. Windows only tells
+/// tool windows and owned windows, which are reported as utility windows and dialogs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum WindowType {
+	/// A top-level application window, also assumed for windows that do not say.
+	#[default]
+	Normal,
+	/// The desktop background window.
+	Desktop,
+	/// Panels and docks.
+	Dock,
+	/// A torn off toolbar.
+	Toolbar,
+	/// A torn off menu.
+	Menu,
+	/// A palette or tool window accompanying the main window.
+	Utility,
+	Splash,
+	Dialog,
+	DropdownMenu,
+	PopupMenu,
+	Tooltip,
+	Notification,
+	/// The list of a combo box.
+	Combo,
+	/// The icon dragged along in drag and drop.
+	Dnd,
+}
+
+/// A virtual desktop, or workspace, as listed by [`ConnectionTrait::desktops`](crate::ConnectionTrait::desktops).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Desktop {
+	/// What [`WindowInfo::desktop`] refers to.
+	pub index: u32,
+	pub name: Option<String>,
+	/// Whether this is the desktop currently shown.
+	pub current: bool,
+}
+
+/// Where the title of a window was read from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum TitleSource {
+	/// The platform has a single notion of a window title.
+	#[default]
+	Native,
+	/// The EWMH `_NET_WM_NAME` property, always UTF-8.
+	NetWmName,
+	/// The legacy ICCCM `WM_NAME` property, decoded from Latin-1 `STRING`,
+	/// `COMPOUND_TEXT` or `UTF8_STRING`.
+	WmName,
+	/// The window has no title at all.
+	Untitled,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[cfg(feature = "serde")]
+	fn window() -> WindowInfo {
+		WindowInfo {
+			id: 0x3a00007,
+			title: "~/src \"main\"".into(),
+			title_source: TitleSource::NetWmName,
+			pid: Some(4242),
+			class: Some("Alacritty".into()),
+			instance: Some("alacritty".into()),
+			visible: true,
+			state: WindowState::MAXIMIZED | WindowState::URGENT,
+			window_type: WindowType::DropdownMenu,
+			geometry: Some(Geometry { x: -10, y: 20, width: 1280, height: 720 }),
+			screen: Some(0),
+			desktop: Some(WindowInfo::ALL_DESKTOPS),
+		}
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn test_serde_schema() {
+		let json = serde_json::to_string(&window()).unwrap();
+		assert_eq!(json, concat!(
+			r#"{"id":60817415,"title":"~/src \"main\"","title_source":"net_wm_name","pid":4242,"class":"Alacritty","#,
+			r#""instance":"alacritty","visible":true,"state":["maximized","urgent"],"window_type":"dropdown_menu","#,
+			r#""geometry":{"x":-10,"y":20,"width":1280,"height":720},"screen":0,"desktop":4294967295}"#,
+		));
+		assert_eq!(serde_json::from_str::<WindowInfo>(&json).unwrap(), window());
+		let desktop = Desktop { index: 1, name: Some("web".into()), current: true };
+		assert_eq!(serde_json::to_string(&desktop).unwrap(), r#"{"index":1,"name":"web","current":true}"#);
+		assert_eq!(serde_json::from_str::<Desktop>(r#"{"index":1,"name":"web","current":true}"#).unwrap(), desktop);
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn test_serde_defaults() {
+		let window: WindowInfo = serde_json::from_str(r#"{"id":1,"title":"a","state":[]}"#).unwrap();
+		assert_eq!(window, WindowInfo { id: 1, title: "a".into(), ..WindowInfo::default() });
+		assert!(serde_json::from_str::<WindowInfo>(r#"{"window_type":"sideways"}"#).is_err());
+		assert!(serde_json::from_str::<WindowInfo>(r#"{"state":["MINIMIZED"]}"#).is_err());
+		assert!(serde_json::from_str::<WindowInfo>(r#"{"state":["upside_down"]}"#).is_err());
+		let window: WindowInfo = serde_json::from_str(r#"{"state":["skip_taskbar","hidden"]}"#).unwrap();
+		assert_eq!(window.state, WindowState::SKIP_TASKBAR | WindowState::HIDDEN);
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn test_readme_schema() {
+		let window = WindowInfo {
+			id: 60817415,
+			title: "~/src".into(),
+			title_source: TitleSource::NetWmName,
+			pid: Some(4242),
+			class: Some("Alacritty".into()),
+			instance: Some("alacritty".into()),
+			visible: true,
+			state: WindowState::MAXIMIZED | WindowState::URGENT,
+			window_type: WindowType::Normal,
+			geometry: Some(Geometry { x: -10, y: 20, width: 1280, height: 720 }),
+			screen: Some(0),
+			desktop: Some(1),
+		};
+		let readme = include_str!("../README.md");
+		let start = readme.find("```json\n").unwrap() + "```json\n".len();
+		let documented: serde_json::Value = serde_json::from_str(&readme[start..start + readme[start..].find("```").unwrap()]).unwrap();
+		assert_eq!(serde_json::to_value(&window).unwrap(), documented);
+		assert_eq!(serde_json::from_value::<WindowInfo>(documented).unwrap(), window);
+	}
+
+	#[cfg(target_os = "linux")]
+	#[test]
+	fn test_executable() {
+		let window = WindowInfo { pid: Some(std::process::id()), ..WindowInfo::default() };
+		assert_eq!(window.executable(), std::env::current_exe().ok());
+		assert_eq!(WindowInfo::default().executable(), None);
+	}
+}
@@ -1,31 +1,1506 @@
-use xcb::{Connection as XConnection, xproto::{Atom, ATOM_WINDOW, get_property, intern_atom, Window}};
+use std::collections::VecDeque;
+#[cfg(feature = "async")]
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
 
-use crate::{ConnectionTrait, Result};
+#[cfg(feature = "async")]
+use async_io::Async;
+#[cfg(feature = "async")]
+use async_lock::Mutex;
+#[cfg(feature = "async")]
+use futures_lite::future;
+#[cfg(feature = "async")]
+use event_listener::Event;
+
+use xcb::{Connection as XConnection, x::{self, Atom, Window}, Xid, XidNew};
+
+use crate::{ConnectionTrait, Desktop, Error, Events, Geometry, Result, TitleSource, WindowEvent, WindowInfo, WindowQuery, WindowState, WindowType, encoding, event::{Poll, Snapshot, POLL_INTERVAL}};
+
+/// Properties are fetched in chunks of this many 32 bit units.
+const CHUNK_LENGTH: u32 = 4096;
+/// The default limit of bytes read from a single property, which keeps a misbehaving
+/// client from making us buffer arbitrary amounts of data.
+const DEFAULT_MAX_PROPERTY_SIZE: u32 = 1 << 20;
+/// The `_NET_WM_WINDOW_TYPE` atoms and what they stand for.
+const WINDOW_TYPES: [(&str, WindowType); 14] = [
+	("_NET_WM_WINDOW_TYPE_NORMAL", WindowType::Normal),
+	("_NET_WM_WINDOW_TYPE_DESKTOP", WindowType::Desktop),
+	("_NET_WM_WINDOW_TYPE_DOCK", WindowType::Dock),
+	("_NET_WM_WINDOW_TYPE_TOOLBAR", WindowType::Toolbar),
+	("_NET_WM_WINDOW_TYPE_MENU", WindowType::Menu),
+	("_NET_WM_WINDOW_TYPE_UTILITY", WindowType::Utility),
+	("_NET_WM_WINDOW_TYPE_SPLASH", WindowType::Splash),
+	("_NET_WM_WINDOW_TYPE_DIALOG", WindowType::Dialog),
+	("_NET_WM_WINDOW_TYPE_DROPDOWN_MENU", WindowType::DropdownMenu),
+	("_NET_WM_WINDOW_TYPE_POPUP_MENU", WindowType::PopupMenu),
+	("_NET_WM_WINDOW_TYPE_TOOLTIP", WindowType::Tooltip),
+	("_NET_WM_WINDOW_TYPE_NOTIFICATION", WindowType::Notification),
+	("_NET_WM_WINDOW_TYPE_COMBO", WindowType::Combo),
+	("_NET_WM_WINDOW_TYPE_DND", WindowType::Dnd),
+];
+/// The `WM_STATE` of iconified windows.
+const ICONIC_STATE: u32 = 3;
+/// The `WM_HINTS` flag of windows asking for attention.
+const URGENCY_HINT: u32 = 1 << 8;
+
+/// How the X11 backend finds the client windows, chosen when connecting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Discovery {
+	/// Reading `_NET_CLIENT_LIST`, which the window manager advertises in `_NET_SUPPORTED`.
+	ClientList,
+	/// Walking the window tree below every root for the windows carrying `WM_STATE`, for
+	/// bare X servers and window managers without EWMH support.
+	TreeWalk,
+}
 
 pub struct Connection {
 	connection: XConnection,
+	discovery: Discovery,
+	/// Whether `_NET_ACTIVE_WINDOW` is advertised, the input focus is followed otherwise.
+	active_window_supported: bool,
+	/// Whether `_NET_CLIENT_LIST_STACKING` is advertised, the window tree is walked otherwise.
+	stacking_supported: bool,
+	/// Whether the server offers the X-Resource extension to look up the pid of a client.
+	#[cfg(feature = "xres")]
+	client_ids_supported: bool,
 	client_list: Atom,
+	client_list_stacking: Atom,
+	active_window: Atom,
 	string: Atom,
+	compound_text: Atom,
 	window_name: Atom,
+	window_pid: Atom,
+	window_desktop: Atom,
+	number_of_desktops: Atom,
+	current_desktop: Atom,
+	desktop_names: Atom,
+	frame_extents: Atom,
+	wm_state: Atom,
+	states: StateAtoms,
+	window_type: Atom,
+	window_types: Vec<(Atom, WindowType)>,
+	/// In 32 bit units, as `GetProperty` counts them.
+	max_property_length: u32,
+	/// The only screen enumerated, all of them if `None`.
+	screen: Option<usize>,
 }
 
 impl ConnectionTrait for Connection {
 	fn new() -> Result<Self> {
-		let connection = XConnection::connect(None)?.0;
-		let client_list = intern_atom(&connection, false, "_NET_CLIENT_LIST").get_reply()?.atom();
-		let string = intern_atom(&connection, false, "UTF8_STRING").get_reply()?.atom();
-		let window_name = intern_atom(&connection, false, "_NET_WM_NAME").get_reply()?.atom();
-		Ok(Self { connection, client_list, string, window_name })
-	}
-	fn window_titles(&self) -> Result<Vec<String>> {
-		let titles = self.connection.get_setup().roots()
-			.map(|screen| screen.root())
-			.map(|window| get_property(&self.connection, false, window, self.client_list, ATOM_WINDOW, 0, 1024))
-			.filter_map(|cookie| cookie.get_reply().ok())
-			.flat_map(|reply| reply.value().to_vec().into_iter())
-			.filter_map(|window: Window| get_property(&self.connection, false, window, self.window_name, self.string, 0, 1024).get_reply().ok())
-			.filter_map(|value| String::from_utf8(value.value().to_vec()).ok())
+		Self::connect(None)
+	}
+	fn windows(&self) -> Result<Vec<WindowInfo>> {
+		let requests = self.window_requests(self.clients()?);
+		Ok(self.window_replies(self.locate_all(requests)))
+	}
+	fn windows_stacked(&self) -> Result<Vec<WindowInfo>> {
+		let requests = self.window_requests(self.stacked_clients()?);
+		Ok(stacked(self.window_replies(self.locate_all(requests))))
+	}
+	/// Looks at the process, class, visibility and desktop of every client first and only
+	/// fetches titles and geometry of the windows that pass, at the cost of a round trip.
+	fn query(&self, query: &WindowQuery) -> Result<Vec<WindowInfo>> {
+		if !query.filters_untitled() {
+			return Ok(self.windows()?.into_iter().filter(|window| query.matches(window)).collect());
+		}
+		let requests = self.properties_requests(self.clients()?);
+		let requests = self.candidates(requests, query);
+		let windows = self.window_replies(self.locate_all(requests));
+		Ok(windows.into_iter().filter(|window| query.matches(window)).collect())
+	}
+	fn active_window(&self) -> Result<Option<WindowInfo>> {
+		let active = match self.active_window_supported {
+			true => self.active_client(self.root_requests(self.active_window, x::ATOM_WINDOW))?,
+			false => self.focused_client()?,
+		};
+		match active {
+			Some(window) => match self.window_info(window) {
+				Ok(window) => Ok(self.on_screen(window)),
+				// The focus is about to move on from a window destroyed in the meantime.
+				Err(Error::Protocol(_)) if self.destroyed(self.destroyed_request(window)) => Ok(None),
+				Err(error) => Err(error),
+			},
+			None => Ok(None),
+		}
+	}
+	/// Reads the desktops of the first enumerated screen, window managers running one
+	/// instance per screen keep separate desktops on each.
+	fn desktops(&self) -> Result<Vec<Desktop>> {
+		self.desktops_reply(self.desktops_request())
+	}
+	fn events(&self) -> Result<Events<'_>> {
+		// Without a client list to watch, changes are only noticed by comparing window lists.
+		if self.discovery == Discovery::TreeWalk || !self.active_window_supported {
+			return Ok(Box::new(Poll::new(self, POLL_INTERVAL)?));
+		}
+		for root in self.roots() {
+			self.select_property_changes(root);
+		}
+		let windows = self.windows()?;
+		windows.iter().for_each(|window| self.select_property_changes(window_from_id(window.id)));
+		self.connection.flush()?;
+		let snapshot = Snapshot::new(windows, self.active_window()?);
+		Ok(Box::new(PropertyEvents { connection: self, snapshot, pending: VecDeque::new() }))
+	}
+}
+
+impl Connection {
+	/// Connects to the X server named by `display`, or by `$DISPLAY` if `None`.
+	pub(crate) fn connect(display: Option<&str>) -> Result<Self> {
+		#[cfg(feature = "xres")]
+		let connection = XConnection::connect_with_extensions(display, &[], &[xcb::Extension::Res])?.0;
+		#[cfg(not(feature = "xres"))]
+		let connection = XConnection::connect(display)?.0;
+		Self::from_connection(connection)
+	}
+
+	/// Interns the atoms used and looks at what the window manager supports.
+	fn from_connection(connection: XConnection) -> Result<Self> {
+		let client_list = intern_atom(&connection, "_NET_CLIENT_LIST")?;
+		let client_list_stacking = intern_atom(&connection, "_NET_CLIENT_LIST_STACKING")?;
+		let active_window = intern_atom(&connection, "_NET_ACTIVE_WINDOW")?;
+		let string = intern_atom(&connection, "UTF8_STRING")?;
+		let compound_text = intern_atom(&connection, "COMPOUND_TEXT")?;
+		let window_name = intern_atom(&connection, "_NET_WM_NAME")?;
+		let window_pid = intern_atom(&connection, "_NET_WM_PID")?;
+		let window_desktop = intern_atom(&connection, "_NET_WM_DESKTOP")?;
+		let number_of_desktops = intern_atom(&connection, "_NET_NUMBER_OF_DESKTOPS")?;
+		let current_desktop = intern_atom(&connection, "_NET_CURRENT_DESKTOP")?;
+		let desktop_names = intern_atom(&connection, "_NET_DESKTOP_NAMES")?;
+		let frame_extents = intern_atom(&connection, "_NET_FRAME_EXTENTS")?;
+		let wm_state = intern_atom(&connection, "WM_STATE")?;
+		let states = StateAtoms {
+			state: intern_atom(&connection, "_NET_WM_STATE")?,
+			hidden: intern_atom(&connection, "_NET_WM_STATE_HIDDEN")?,
+			maximized_vert: intern_atom(&connection, "_NET_WM_STATE_MAXIMIZED_VERT")?,
+			maximized_horz: intern_atom(&connection, "_NET_WM_STATE_MAXIMIZED_HORZ")?,
+			fullscreen: intern_atom(&connection, "_NET_WM_STATE_FULLSCREEN")?,
+			demands_attention: intern_atom(&connection, "_NET_WM_STATE_DEMANDS_ATTENTION")?,
+			skip_taskbar: intern_atom(&connection, "_NET_WM_STATE_SKIP_TASKBAR")?,
+		};
+		let window_type = intern_atom(&connection, "_NET_WM_WINDOW_TYPE")?;
+		let window_types = intern_atoms(&connection, WINDOW_TYPES.iter().map(|&(name, _)| name))?
+			.into_iter()
+			.zip(WINDOW_TYPES.iter().map(|&(_, window_type)| window_type))
+			.collect();
+		let supported = intern_atom(&connection, "_NET_SUPPORTED")?;
+		#[cfg(feature = "xres")]
+		let client_ids_supported = connection.active_extensions().any(|extension| extension == xcb::Extension::Res);
+		let mut connection = Self {
+			connection,
+			discovery: Discovery::TreeWalk,
+			active_window_supported: false,
+			stacking_supported: false,
+			#[cfg(feature = "xres")]
+			client_ids_supported,
+			client_list, client_list_stacking, active_window, string, compound_text, window_name, window_pid,
+			window_desktop, number_of_desktops, current_desktop, desktop_names, frame_extents, wm_state, states,
+			window_type, window_types,
+			max_property_length: DEFAULT_MAX_PROPERTY_SIZE / 4,
+			screen: None,
+		};
+		let supported = match connection.root_property::<Atom>(supported, x::ATOM_ATOM, "_NET_SUPPORTED") {
+			Err(Error::MissingEwmh(_)) => Vec::new(),
+			supported => supported?,
+		};
+		if supported.contains(&client_list) {
+			connection.discovery = Discovery::ClientList;
+		}
+		connection.active_window_supported = supported.contains(&active_window);
+		connection.stacking_supported = supported.contains(&client_list_stacking);
+		Ok(connection)
+	}
+
+	pub fn set_max_property_size(&mut self, bytes: u32) {
+		self.max_property_length = (bytes / 4).max(1);
+	}
+
+	pub fn discovery(&self) -> Discovery {
+		self.discovery
+	}
+
+	pub fn set_screen(&mut self, screen: Option<usize>) -> Result<()> {
+		self.screen = valid_screen(screen, self.connection.get_setup().roots().count())?;
+		Ok(())
+	}
+
+	/// The root windows of the enumerated screens.
+	fn roots(&self) -> Vec<Window> {
+		self.connection.get_setup().roots().enumerate()
+			.filter(|&(index, _)| self.screen.is_none_or(|screen| screen == index))
+			.map(|(_, screen)| screen.root())
+			.collect()
+	}
+
+	fn clients(&self) -> Result<Vec<Window>> {
+		match self.discovery {
+			Discovery::ClientList => self.root_property(self.client_list, x::ATOM_WINDOW, "_NET_CLIENT_LIST"),
+			Discovery::TreeWalk => Ok(self.tree_clients()),
+		}
+	}
+
+	/// The clients of every root from the bottom of the stack up.
+	fn stacked_clients(&self) -> Result<Vec<Window>> {
+		match self.stacking_supported {
+			true => self.root_property(self.client_list_stacking, x::ATOM_WINDOW, "_NET_CLIENT_LIST_STACKING"),
+			// The children of a root are listed from the bottom, and so are the clients found below them.
+			false => Ok(self.tree_clients()),
+		}
+	}
+
+	/// Finds the client below every top-level window the way `XmuClientWindow` does: the
+	/// toplevel itself if it carries `WM_STATE`, else the first descendant that does, searched
+	/// breadth first. Each level of the search is requested for all toplevels at once.
+	fn tree_clients(&self) -> Vec<Window> {
+		let mut walk = self.tree_walk();
+		while !walk.is_done() {
+			walk = self.walk(walk);
+		}
+		walk.clients()
+	}
+
+	/// Starts the search of [`Connection::tree_clients`] by asking for the toplevels of every root.
+	fn tree_walk(&self) -> TreeWalk {
+		let roots = self.roots().into_iter().map(|window| self.connection.send_request(&x::QueryTree { window })).collect();
+		TreeWalk { found: Vec::new(), round: WalkRound::Toplevels(roots) }
+	}
+
+	/// Takes the replies of one round of the search and sends the requests of the next.
+	fn walk(&self, TreeWalk { mut found, round }: TreeWalk) -> TreeWalk {
+		let round = match round {
+			WalkRound::Toplevels(cookies) => {
+				let mut toplevels = Vec::new();
+				for cookie in cookies {
+					if let Ok(tree) = self.connection.wait_for_reply(cookie) {
+						toplevels.extend_from_slice(tree.children());
+					}
+				}
+				found = vec![None; toplevels.len()];
+				self.states_round(toplevels.into_iter().enumerate().collect())
+			},
+			WalkRound::States(candidates) => {
+				let mut unresolved = Vec::new();
+				for (toplevel, window, cookie) in candidates {
+					let has_state = self.connection.wait_for_reply(cookie).is_ok_and(|reply| reply.r#type() != x::ATOM_NONE);
+					match found[toplevel] {
+						Some(_) => {},
+						None if has_state => found[toplevel] = Some(window),
+						None => unresolved.push((toplevel, window)),
+					}
+				}
+				unresolved.retain(|&(toplevel, _)| found[toplevel].is_none());
+				match unresolved.is_empty() {
+					true => WalkRound::Done,
+					false => WalkRound::Children(unresolved.into_iter()
+						.map(|(toplevel, window)| (toplevel, self.connection.send_request(&x::QueryTree { window })))
+						.collect()),
+				}
+			},
+			WalkRound::Children(cookies) => {
+				let mut children = Vec::new();
+				for (toplevel, cookie) in cookies {
+					// Windows destroyed since they were listed simply have no children.
+					if let Ok(tree) = self.connection.wait_for_reply(cookie) {
+						children.extend(tree.children().iter().map(|&child| (toplevel, child)));
+					}
+				}
+				self.states_round(children)
+			},
+			WalkRound::Done => WalkRound::Done,
+		};
+		TreeWalk { found, round }
+	}
+
+	fn states_round(&self, candidates: Vec<(usize, Window)>) -> WalkRound {
+		match candidates.is_empty() {
+			true => WalkRound::Done,
+			false => WalkRound::States(candidates.into_iter()
+				.map(|(toplevel, window)| (toplevel, window, self.wm_state_request(window)))
+				.collect()),
+		}
+	}
+
+	/// The client holding the input focus, found by climbing from the focused window
+	/// until one carrying `WM_STATE` turns up.
+	fn focused_client(&self) -> Result<Option<Window>> {
+		let mut climb = Climb::Focus(self.connection.send_request(&x::GetInputFocus {}));
+		loop {
+			climb = match self.climb(climb)? {
+				Climb::Done(window) => return Ok(window),
+				climb => climb,
+			};
+		}
+	}
+
+	/// Takes the replies about one level of [`Connection::focused_client`] and asks about the next.
+	fn climb(&self, climb: Climb) -> Result<Climb> {
+		match climb {
+			Climb::Focus(cookie) => Ok(self.climb_to(self.connection.wait_for_reply(cookie)?.focus())),
+			Climb::Level(window, state, tree) => {
+				let state = self.connection.wait_for_reply(state);
+				let tree = self.connection.wait_for_reply(tree)?;
+				if state?.r#type() != x::ATOM_NONE {
+					return Ok(Climb::Done(Some(window)));
+				}
+				match tree.parent() == tree.root() || tree.parent().is_none() {
+					true => Ok(Climb::Done(None)),
+					false => Ok(self.climb_to(tree.parent())),
+				}
+			},
+			climb => Ok(climb),
+		}
+	}
+
+	/// Asks whether `window` is a client and for its parent in one go.
+	fn climb_to(&self, window: Window) -> Climb {
+		// `None` and `PointerRoot` are the only focus values that are not windows.
+		match window.resource_id() > 1 {
+			true => Climb::Level(window, self.wm_state_request(window), self.connection.send_request(&x::QueryTree { window })),
+			false => Climb::Done(None),
+		}
+	}
+
+	/// Asks for the type of `WM_STATE` only, which is `None` unless the window is a client.
+	fn wm_state_request(&self, window: Window) -> x::GetPropertyCookie {
+		self.connection.send_request(&x::GetProperty {
+			delete: false, window, property: self.wm_state, r#type: x::ATOM_ANY, long_offset: 0, long_length: 0,
+		})
+	}
+
+	/// Asks about a window only to learn whether it still exists, see [`Connection::destroyed`].
+	fn destroyed_request(&self, window: Window) -> x::GetWindowAttributesCookie {
+		self.connection.send_request(&x::GetWindowAttributes { window })
+	}
+
+	fn destroyed(&self, cookie: x::GetWindowAttributesCookie) -> bool {
+		matches!(self.connection.wait_for_reply(cookie), Err(xcb::Error::Protocol(xcb::ProtocolError::X(x::Error::Window(_), _))))
+	}
+
+	fn window_info(&self, window: Window) -> Result<WindowInfo> {
+		self.window_reply(self.window_request(window))
+	}
+
+	fn window_requests(&self, windows: Vec<Window>) -> Vec<WindowRequest> {
+		windows.into_iter().map(|window| self.window_request(window)).collect()
+	}
+
+	fn properties_requests(&self, windows: Vec<Window>) -> Vec<(Window, PropertiesRequest)> {
+		windows.into_iter().map(|window| (window, self.properties_request(window))).collect()
+	}
+
+	/// Requests the title and geometry of the windows whose properties pass the query.
+	fn candidates(&self, requests: Vec<(Window, PropertiesRequest)>, query: &WindowQuery) -> Vec<WindowRequest> {
+		let candidates: Vec<_> = requests.into_iter()
+			.filter_map(|(window, request)| self.properties_reply(window, request).ok())
+			.filter(|window| query.matches_untitled(window))
 			.collect();
-		Ok(titles)
+		candidates.into_iter().map(|window| self.describe(window)).collect()
+	}
+
+	fn locate_all(&self, requests: Vec<WindowRequest>) -> Vec<WindowRequest> {
+		requests.into_iter().map(|request| self.locate(request)).collect()
+	}
+
+	/// The records of every window that still existed when its replies came in.
+	fn window_replies(&self, requests: Vec<WindowRequest>) -> Vec<WindowInfo> {
+		requests.into_iter().filter_map(|request| self.window_reply(request).ok()).collect()
+	}
+
+	/// Sends every request needed to describe `window` without waiting for any reply, so that
+	/// the requests for many windows share a single round trip.
+	fn window_request(&self, window: Window) -> WindowRequest {
+		self.details_request(window, Properties::Pending(Box::new(self.properties_request(window))))
+	}
+
+	/// Requests the title and geometry of a window whose other properties are known already.
+	fn describe(&self, properties: WindowInfo) -> WindowRequest {
+		self.details_request(window_from_id(properties.id), Properties::Known(properties))
+	}
+
+	fn details_request(&self, window: Window, properties: Properties) -> WindowRequest {
+		WindowRequest {
+			window,
+			properties,
+			name: self.request(window, self.window_name, self.string),
+			legacy_name: self.request(window, x::ATOM_WM_NAME, x::ATOM_ANY),
+			frame_extents: self.request(window, self.frame_extents, x::ATOM_CARDINAL),
+			location: Location::Size(self.connection.send_request(&x::GetGeometry { drawable: x::Drawable::Window(window) })),
+		}
+	}
+
+	/// Requests the small properties a query can filter on before fetching titles.
+	fn properties_request(&self, window: Window) -> PropertiesRequest {
+		PropertiesRequest {
+			pid: self.request(window, self.window_pid, x::ATOM_CARDINAL),
+			class: self.request(window, x::ATOM_WM_CLASS, x::ATOM_STRING),
+			desktop: self.request(window, self.window_desktop, x::ATOM_CARDINAL),
+			net_state: self.request(window, self.states.state, x::ATOM_ATOM),
+			wm_state: self.request(window, self.wm_state, self.wm_state),
+			hints: self.request(window, x::ATOM_WM_HINTS, x::ATOM_WM_HINTS),
+			window_type: self.request(window, self.window_type, x::ATOM_ATOM),
+			transient_for: self.request(window, x::ATOM_WM_TRANSIENT_FOR, x::ATOM_WINDOW),
+			#[cfg(feature = "xres")]
+			client_pid: self.client_ids_supported.then(|| self.connection.send_request(&xcb::res::QueryClientIds {
+				specs: &[xcb::res::ClientIdSpec { client: window.resource_id(), mask: xcb::res::ClientIdMask::LOCAL_CLIENT_PID }],
+			})),
+			attributes: self.connection.send_request(&x::GetWindowAttributes { window }),
+		}
+	}
+
+	/// A window record with everything but the title and geometry filled in.
+	fn properties_reply(&self, window: Window, request: PropertiesRequest) -> Result<WindowInfo> {
+		// Wait for every reply before bailing out, so none is left queued in the connection.
+		let pid = self.reply::<u32>(request.pid);
+		let class = self.reply::<u8>(request.class);
+		let desktop = self.reply::<u32>(request.desktop);
+		let net_state = self.reply::<Atom>(request.net_state);
+		let wm_state = self.reply::<u32>(request.wm_state);
+		let hints = self.reply::<u32>(request.hints);
+		let types = self.reply::<Atom>(request.window_type);
+		let transient_for = self.reply::<Window>(request.transient_for);
+		#[cfg(feature = "xres")]
+		let client_pid = request.client_pid.map(|cookie| self.connection.wait_for_reply(cookie));
+		let attributes = self.connection.wait_for_reply(request.attributes);
+		let pid = pid?.1.first().copied();
+		// The server knows the pid of local clients for sure, `_NET_WM_PID` is only what the client claims.
+		#[cfg(feature = "xres")]
+		let pid = client_pid.and_then(|reply| reply.ok())
+			.and_then(|reply| reply.ids().find(|id| id.spec().mask.contains(xcb::res::ClientIdMask::LOCAL_CLIENT_PID))
+				.and_then(|id| id.value().first().copied()))
+			.or(pid);
+		let (instance, class) = wm_class(&class?.1);
+		Ok(WindowInfo {
+			id: window.resource_id().into(),
+			pid, class, instance,
+			visible: attributes?.map_state() == x::MapState::Viewable,
+			desktop: desktop?.1.first().copied(),
+			state: self.states.window_state(&net_state?.1, &wm_state?.1, &hints?.1),
+			window_type: window_type(&self.window_types, &types?.1, !transient_for?.1.is_empty()),
+			..WindowInfo::default()
+		})
+	}
+
+	/// Waits for the size of the window and asks where it sits on its root, which only the
+	/// geometry reply names. Doing this for every request before waiting on any other reply
+	/// keeps the second round trip shared as well.
+	fn locate(&self, mut request: WindowRequest) -> WindowRequest {
+		request.location = match request.location {
+			Location::Size(cookie) => match self.connection.wait_for_reply(cookie) {
+				Ok(geometry) => {
+					let translation = self.connection.send_request(&x::TranslateCoordinates {
+						src_window: request.window, dst_window: geometry.root(), src_x: 0, src_y: 0,
+					});
+					Location::Position(geometry, translation)
+				},
+				Err(error) => Location::Failed(error.into()),
+			},
+			location => location,
+		};
+		request
+	}
+
+	fn window_reply(&self, request: WindowRequest) -> Result<WindowInfo> {
+		let request = self.locate(request);
+		let properties = match request.properties {
+			Properties::Pending(properties) => self.properties_reply(request.window, *properties),
+			Properties::Known(properties) => Ok(properties),
+		};
+		let name = self.reply::<u8>(request.name);
+		let legacy_name = self.reply::<u8>(request.legacy_name);
+		let frame_extents = self.reply::<u32>(request.frame_extents);
+		let (geometry, translation) = match request.location {
+			Location::Position(geometry, translation) => (geometry, self.connection.wait_for_reply(translation)),
+			Location::Failed(error) => return Err(error),
+			Location::Size(_) => unreachable!("located above"),
+		};
+		let (title, title_source) = title(self.string, self.compound_text, name?, legacy_name?);
+		let frame_extents = frame_extents?.1;
+		let translation = translation?;
+		let screen = self.connection.get_setup().roots().position(|screen| screen.root() == geometry.root());
+		let geometry = framed(translation.dst_x(), translation.dst_y(), geometry.width(), geometry.height(), &frame_extents);
+		Ok(WindowInfo { title, title_source, geometry: Some(geometry), screen, ..properties? })
+	}
+
+	fn select_property_changes(&self, window: Window) {
+		self.connection.send_request(&x::ChangeWindowAttributes {
+			window,
+			value_list: &[x::Cw::EventMask(x::EventMask::PROPERTY_CHANGE)],
+		});
+	}
+
+	/// Collects an EWMH hint from every root, failing if no root carries it at all.
+	fn root_property<P: x::PropEl + Clone>(&self, property: Atom, r#type: Atom, name: &'static str) -> Result<Vec<P>> {
+		self.root_reply(self.root_requests(property, r#type), name)
+	}
+
+	/// Requests a property of every enumerated root at once.
+	fn root_requests(&self, property: Atom, r#type: Atom) -> Vec<PropertyRequest> {
+		self.roots().into_iter().map(|root| self.request(root, property, r#type)).collect()
+	}
+
+	fn root_reply<P: x::PropEl + Clone>(&self, requests: Vec<PropertyRequest>, name: &'static str) -> Result<Vec<P>> {
+		let replies: Vec<_> = requests.into_iter().map(|request| self.reply::<P>(request)).collect();
+		let mut supported = false;
+		let mut values = Vec::new();
+		for reply in replies {
+			let (r#type, windows) = reply?;
+			supported |= r#type != x::ATOM_NONE;
+			values.extend(windows);
+		}
+		match supported {
+			true => Ok(values),
+			false => Err(Error::MissingEwmh(name)),
+		}
+	}
+
+	/// The window named by `_NET_ACTIVE_WINDOW` on any of the roots.
+	fn active_client(&self, requests: Vec<PropertyRequest>) -> Result<Option<Window>> {
+		Ok(self.root_reply::<Window>(requests, "_NET_ACTIVE_WINDOW")?.into_iter().find(|window| !window.is_none()))
+	}
+
+	/// Drops windows outside the enumerated screen, where the input focus may well be.
+	fn on_screen(&self, window: WindowInfo) -> Option<WindowInfo> {
+		Some(window).filter(|window| self.screen.is_none_or(|screen| window.screen == Some(screen)))
+	}
+
+	/// Requests the desktop count, the current desktop and the desktop names at once.
+	fn desktops_request(&self) -> [Vec<PropertyRequest>; 3] {
+		[
+			self.root_requests(self.number_of_desktops, x::ATOM_CARDINAL),
+			self.root_requests(self.current_desktop, x::ATOM_CARDINAL),
+			self.root_requests(self.desktop_names, self.string),
+		]
+	}
+
+	fn desktops_reply(&self, [count, current, names]: [Vec<PropertyRequest>; 3]) -> Result<Vec<Desktop>> {
+		let count = self.root_reply::<u32>(count, "_NET_NUMBER_OF_DESKTOPS");
+		let current = match self.root_reply::<u32>(current, "_NET_CURRENT_DESKTOP") {
+			Err(Error::MissingEwmh(_)) => Ok(Vec::new()),
+			current => current,
+		};
+		let names = match self.root_reply::<u8>(names, "_NET_DESKTOP_NAMES") {
+			Err(Error::MissingEwmh(_)) => Ok(Vec::new()),
+			names => names,
+		};
+		Ok(desktops(count?.first().copied().unwrap_or(0), current?.first().copied(), &names?))
+	}
+
+	/// Requests the first chunk of a property.
+	fn request(&self, window: Window, property: Atom, r#type: Atom) -> PropertyRequest {
+		let chunk = Chunk::Requested(self.chunk_request(window, property, r#type, 0));
+		PropertyRequest { window, property, r#type, offset: 0, chunks: Vec::new(), chunk }
+	}
+
+	fn chunk_request(&self, window: Window, property: Atom, r#type: Atom, offset: u32) -> x::GetPropertyCookie {
+		self.connection.send_request(&x::GetProperty {
+			delete: false, window, property, r#type, long_offset: offset,
+			long_length: CHUNK_LENGTH.min(self.max_property_length - offset),
+		})
+	}
+
+	/// Waits for the chunk in flight, requesting further chunks while the server reports
+	/// `bytes_after`, up to the configured maximum size.
+	fn reply<P: x::PropEl + Clone>(&self, request: PropertyRequest) -> Result<(Atom, Vec<P>)> {
+		let PropertyRequest { window, property, r#type, mut offset, chunks, mut chunk } = request;
+		let mut values = Vec::new();
+		for reply in &chunks {
+			values.extend_from_slice(self::values::<P>(reply)?);
+		}
+		loop {
+			let reply = match chunk {
+				Chunk::Requested(cookie) => self.connection.wait_for_reply(cookie)?,
+				Chunk::Received(reply) => reply?,
+			};
+			let received = self::values::<P>(&reply)?;
+			values.extend_from_slice(received);
+			match next_chunk(r#type, reply.r#type(), received.len(), reply.bytes_after(), offset, self.max_property_length) {
+				Some(next) => offset = next,
+				None => return Ok((reply.r#type(), values)),
+			}
+			chunk = Chunk::Requested(self.chunk_request(window, property, r#type, offset));
+		}
+	}
+
+	/// Takes the chunk in flight off the connection if it has arrived and requests the next
+	/// one if the property goes on, returning whether there is a chunk left to wait for.
+	#[cfg(feature = "async")]
+	fn read_ahead(&self, request: &mut PropertyRequest) -> bool {
+		let reply = match &request.chunk {
+			Chunk::Requested(cookie) => match self.connection.poll_for_reply(cookie) {
+				Some(reply) => reply,
+				None => return true,
+			},
+			Chunk::Received(_) => return false,
+		};
+		let reply = match reply {
+			// The reply counts its value in units of four bytes, which are zero only without a value.
+			Ok(reply) => match next_chunk(request.r#type, reply.r#type(), reply.length() as usize, reply.bytes_after(), request.offset, self.max_property_length) {
+				Some(next) => {
+					request.chunks.push(reply);
+					request.offset = next;
+					request.chunk = Chunk::Requested(self.chunk_request(request.window, request.property, request.r#type, next));
+					return true;
+				},
+				None => Ok(reply),
+			},
+			Err(error) => Err(error),
+		};
+		request.chunk = Chunk::Received(reply);
+		false
+	}
+}
+
+/// A property request in flight, remembering what to ask for if the value spans more than one chunk.
+struct PropertyRequest {
+	window: Window,
+	property: Atom,
+	r#type: Atom,
+	/// Where the chunk in flight starts, with the replies to the chunks before it.
+	offset: u32,
+	chunks: Vec<x::GetPropertyReply>,
+	chunk: Chunk,
+}
+
+/// The chunk of a [`PropertyRequest`] in flight, or its reply once [`Connection::read_ahead`] took it.
+enum Chunk {
+	Requested(x::GetPropertyCookie),
+	#[cfg_attr(not(feature = "async"), allow(dead_code))]
+	Received(xcb::Result<x::GetPropertyReply>),
+}
+
+/// The requests in flight for a single window, see [`Connection::window_request`].
+struct WindowRequest {
+	window: Window,
+	properties: Properties,
+	name: PropertyRequest,
+	legacy_name: PropertyRequest,
+	frame_extents: PropertyRequest,
+	location: Location,
+}
+
+/// The requests for the properties a query filters on, see [`Connection::properties_request`].
+struct PropertiesRequest {
+	pid: PropertyRequest,
+	class: PropertyRequest,
+	desktop: PropertyRequest,
+	net_state: PropertyRequest,
+	wm_state: PropertyRequest,
+	hints: PropertyRequest,
+	window_type: PropertyRequest,
+	transient_for: PropertyRequest,
+	#[cfg(feature = "xres")]
+	client_pid: Option<xcb::res::QueryClientIdsCookie>,
+	attributes: x::GetWindowAttributesCookie,
+}
+
+#[cfg(feature = "async")]
+impl WindowRequest {
+	/// The properties in flight, see [`AsyncConnection::settle_properties`].
+	fn property_requests(&mut self) -> Vec<&mut PropertyRequest> {
+		let mut requests = vec![&mut self.name, &mut self.legacy_name, &mut self.frame_extents];
+		if let Properties::Pending(properties) = &mut self.properties {
+			requests.extend(properties.property_requests());
+		}
+		requests
+	}
+}
+
+#[cfg(feature = "async")]
+impl PropertiesRequest {
+	fn property_requests(&mut self) -> Vec<&mut PropertyRequest> {
+		vec![
+			&mut self.pid, &mut self.class, &mut self.desktop, &mut self.net_state, &mut self.wm_state,
+			&mut self.hints, &mut self.window_type, &mut self.transient_for,
+		]
+	}
+}
+
+/// `_NET_WM_STATE` and the atoms of the states it may list.
+struct StateAtoms {
+	state: Atom,
+	hidden: Atom,
+	maximized_vert: Atom,
+	maximized_horz: Atom,
+	fullscreen: Atom,
+	demands_attention: Atom,
+	skip_taskbar: Atom,
+}
+
+impl StateAtoms {
+	/// Combines `_NET_WM_STATE` with the ICCCM `WM_STATE` and `WM_HINTS`, whose first values are
+	/// the state and the flags.
+	fn window_state(&self, net_state: &[Atom], wm_state: &[u32], hints: &[u32]) -> WindowState {
+		let mut state = WindowState::empty();
+		state.set(WindowState::MINIMIZED, wm_state.first() == Some(&ICONIC_STATE));
+		state.set(WindowState::MAXIMIZED, net_state.contains(&self.maximized_vert) && net_state.contains(&self.maximized_horz));
+		state.set(WindowState::FULLSCREEN, net_state.contains(&self.fullscreen));
+		state.set(WindowState::HIDDEN, net_state.contains(&self.hidden));
+		state.set(WindowState::URGENT, net_state.contains(&self.demands_attention) || hints.first().is_some_and(|flags| flags & URGENCY_HINT != 0));
+		state.set(WindowState::SKIP_TASKBAR, net_state.contains(&self.skip_taskbar));
+		state
+	}
+}
+
+/// The search of [`Connection::tree_clients`] between two round trips.
+struct TreeWalk {
+	/// The client found below each toplevel, in stacking order across the roots.
+	found: Vec<Option<Window>>,
+	round: WalkRound,
+}
+
+impl TreeWalk {
+	fn is_done(&self) -> bool {
+		matches!(self.round, WalkRound::Done)
+	}
+
+	fn clients(self) -> Vec<Window> {
+		self.found.into_iter().flatten().collect()
+	}
+}
+
+/// The requests in flight, each tagged with the index of the toplevel it searches below.
+enum WalkRound {
+	/// The children of every root, which are the toplevels.
+	Toplevels(Vec<x::QueryTreeCookie>),
+	States(Vec<(usize, Window, x::GetPropertyCookie)>),
+	Children(Vec<(usize, x::QueryTreeCookie)>),
+	Done,
+}
+
+/// The climb of [`Connection::focused_client`] between two round trips.
+enum Climb {
+	Focus(x::GetInputFocusCookie),
+	/// Whether the window carries `WM_STATE`, and its parent.
+	Level(Window, x::GetPropertyCookie, x::QueryTreeCookie),
+	Done(Option<Window>),
+}
+
+enum Properties {
+	Pending(Box<PropertiesRequest>),
+	Known(WindowInfo),
+}
+
+/// How far along finding the on-screen position of a window request is, see [`Connection::locate`].
+enum Location {
+	Size(x::GetGeometryCookie),
+	Position(x::GetGeometryReply, x::TranslateCoordinatesCookie),
+	Failed(Error),
+}
+
+/// Follows `PropertyNotify` on the roots for the client list and focus, and on every client for its title.
+struct PropertyEvents<'a> {
+	connection: &'a Connection,
+	snapshot: Snapshot,
+	pending: VecDeque<WindowEvent>,
+}
+
+impl PropertyEvents<'_> {
+	fn property_changed(&mut self, event: &x::PropertyNotifyEvent) -> Result<()> {
+		let connection = self.connection;
+		if event.atom() == connection.client_list {
+			let events = self.snapshot.windows(connection.windows()?);
+			for event in &events {
+				if let WindowEvent::Opened(window) = event {
+					connection.select_property_changes(window_from_id(window.id));
+				}
+			}
+			connection.connection.flush()?;
+			self.pending.extend(events);
+		} else if event.atom() == connection.active_window {
+			self.pending.extend(self.snapshot.active(connection.active_window()?));
+		} else if event.atom() == connection.window_name || event.atom() == x::ATOM_WM_NAME {
+			// The window may already be gone, its removal from the client list follows.
+			if let Ok(window) = connection.window_info(event.window()) {
+				self.pending.extend(self.snapshot.title(window));
+			}
+		}
+		Ok(())
+	}
+}
+
+impl Iterator for PropertyEvents<'_> {
+	type Item = Result<WindowEvent>;
+	fn next(&mut self) -> Option<Self::Item> {
+		loop {
+			if let Some(event) = self.pending.pop_front() {
+				return Some(Ok(event));
+			}
+			let result = match self.connection.connection.wait_for_event() {
+				Ok(xcb::Event::X(x::Event::PropertyNotify(event))) => self.property_changed(&event),
+				// Errors about windows destroyed in the meantime are expected.
+				Ok(_) | Err(xcb::Error::Protocol(_)) => Ok(()),
+				Err(error) => Err(error.into()),
+			};
+			if let Err(error) = result {
+				return Some(Err(error));
+			}
+		}
+	}
+}
+
+/// The X11 backend of [`AsyncConnection`](crate::AsyncConnection), which sends the same requests
+/// as the blocking one but waits for the socket to become readable instead of blocking on replies.
+#[cfg(feature = "async")]
+pub(crate) struct AsyncConnection {
+	// Declared first so that it is deregistered before the connection closes the socket.
+	socket: Async<Socket>,
+	/// Held while reading from the socket, so that whoever waits for it to become readable
+	/// is the only one taking data off it.
+	reader: Mutex<()>,
+	/// Notified after reading from the socket, which may have queued the events someone
+	/// waits for without the lock.
+	read: Event,
+	connection: Connection,
+}
+
+#[cfg(feature = "async")]
+impl AsyncConnection {
+	pub(crate) fn new(connection: Connection) -> Result<Self> {
+		let socket = Async::new(Socket(connection.connection.as_raw_fd())).map_err(io_error)?;
+		Ok(Self { socket, reader: Mutex::new(()), read: Event::new(), connection })
+	}
+
+	pub(crate) async fn windows(&self) -> Result<Vec<WindowInfo>> {
+		self.window_infos(self.clients().await?).await
+	}
+
+	pub(crate) async fn windows_stacked(&self) -> Result<Vec<WindowInfo>> {
+		let clients = match self.connection.stacking_supported {
+			true => {
+				let mut requests = self.connection.root_requests(self.connection.client_list_stacking, x::ATOM_WINDOW);
+				self.settle_properties(requests.iter_mut()).await?;
+				self.connection.root_reply(requests, "_NET_CLIENT_LIST_STACKING")?
+			},
+			false => self.tree_clients().await?,
+		};
+		Ok(stacked(self.window_infos(clients).await?))
+	}
+
+	pub(crate) async fn query(&self, query: &WindowQuery) -> Result<Vec<WindowInfo>> {
+		if !query.filters_untitled() {
+			return Ok(self.windows().await?.into_iter().filter(|window| query.matches(window)).collect());
+		}
+		let mut requests = self.connection.properties_requests(self.clients().await?);
+		self.settle_properties(requests.iter_mut().flat_map(|(_, request)| request.property_requests())).await?;
+		let mut requests = self.connection.candidates(requests, query);
+		self.settle_properties(requests.iter_mut().flat_map(WindowRequest::property_requests)).await?;
+		let requests = self.connection.locate_all(requests);
+		self.settle().await?;
+		let windows = self.connection.window_replies(requests);
+		Ok(windows.into_iter().filter(|window| query.matches(window)).collect())
+	}
+
+	pub(crate) async fn active_window(&self) -> Result<Option<WindowInfo>> {
+		let active = match self.connection.active_window_supported {
+			true => {
+				let mut requests = self.connection.root_requests(self.connection.active_window, x::ATOM_WINDOW);
+				self.settle_properties(requests.iter_mut()).await?;
+				self.connection.active_client(requests)?
+			},
+			false => self.focused_client().await?,
+		};
+		match active {
+			Some(window) => match self.window_info(window).await {
+				Ok(window) => Ok(self.connection.on_screen(window)),
+				Err(Error::Protocol(reason)) => {
+					let cookie = self.connection.destroyed_request(window);
+					self.settle().await?;
+					match self.connection.destroyed(cookie) {
+						true => Ok(None),
+						false => Err(Error::Protocol(reason)),
+					}
+				},
+				Err(error) => Err(error),
+			},
+			None => Ok(None),
+		}
+	}
+
+	pub(crate) async fn desktops(&self) -> Result<Vec<Desktop>> {
+		let mut requests = self.connection.desktops_request();
+		self.settle_properties(requests.iter_mut().flatten()).await?;
+		self.connection.desktops_reply(requests)
+	}
+
+	/// The changes announced by `PropertyNotify`, `None` where the blocking backend would poll.
+	pub(crate) async fn events(&self) -> Result<Option<AsyncPropertyEvents<'_>>> {
+		let connection = &self.connection;
+		if connection.discovery == Discovery::TreeWalk || !connection.active_window_supported {
+			return Ok(None);
+		}
+		for root in connection.roots() {
+			connection.select_property_changes(root);
+		}
+		let windows = self.windows().await?;
+		windows.iter().for_each(|window| connection.select_property_changes(window_from_id(window.id)));
+		connection.connection.flush()?;
+		let snapshot = Snapshot::new(windows, self.active_window().await?);
+		Ok(Some(AsyncPropertyEvents { connection: self, snapshot, pending: VecDeque::new() }))
+	}
+
+	async fn clients(&self) -> Result<Vec<Window>> {
+		match self.connection.discovery {
+			Discovery::ClientList => {
+				let mut requests = self.connection.root_requests(self.connection.client_list, x::ATOM_WINDOW);
+				self.settle_properties(requests.iter_mut()).await?;
+				self.connection.root_reply(requests, "_NET_CLIENT_LIST")
+			},
+			Discovery::TreeWalk => self.tree_clients().await,
+		}
+	}
+
+	/// [`Connection::tree_clients`], waiting for the socket before each level of the tree.
+	async fn tree_clients(&self) -> Result<Vec<Window>> {
+		let mut walk = self.connection.tree_walk();
+		while !walk.is_done() {
+			self.settle().await?;
+			walk = self.connection.walk(walk);
+		}
+		Ok(walk.clients())
+	}
+
+	/// [`Connection::focused_client`], waiting for the socket before each level of the climb.
+	async fn focused_client(&self) -> Result<Option<Window>> {
+		let mut climb = Climb::Focus(self.connection.connection.send_request(&x::GetInputFocus {}));
+		loop {
+			self.settle().await?;
+			climb = match self.connection.climb(climb)? {
+				Climb::Done(window) => return Ok(window),
+				climb => climb,
+			};
+		}
+	}
+
+	async fn window_infos(&self, windows: Vec<Window>) -> Result<Vec<WindowInfo>> {
+		let mut requests = self.connection.window_requests(windows);
+		self.settle_properties(requests.iter_mut().flat_map(WindowRequest::property_requests)).await?;
+		let requests = self.connection.locate_all(requests);
+		self.settle().await?;
+		Ok(self.connection.window_replies(requests))
+	}
+
+	async fn window_info(&self, window: Window) -> Result<WindowInfo> {
+		let mut request = self.connection.window_request(window);
+		self.settle_properties(request.property_requests()).await?;
+		let request = self.connection.locate(request);
+		self.settle().await?;
+		self.connection.window_reply(request)
+	}
+
+	/// [`AsyncConnection::settle`], then again for the further chunks of properties longer
+	/// than one, which [`Connection::reply`] would otherwise block on.
+	async fn settle_properties<'a>(&self, requests: impl IntoIterator<Item = &'a mut PropertyRequest>) -> Result<()> {
+		let mut requests: Vec<_> = requests.into_iter().collect();
+		while !requests.is_empty() {
+			self.settle().await?;
+			requests.retain_mut(|request| self.connection.read_ahead(request));
+		}
+		Ok(())
+	}
+
+	/// Waits until the server has answered every request sent so far. Replies arrive in order, so
+	/// once the reply to a trailing `GetInputFocus` is in, every earlier one has been read into
+	/// the connection and waiting for it returns at once.
+	async fn settle(&self) -> Result<()> {
+		let cookie = self.connection.connection.send_request(&x::GetInputFocus {});
+		self.connection.connection.flush()?;
+		let _reader = self.reader.lock().await;
+		loop {
+			let reply = self.connection.connection.poll_for_reply(&cookie);
+			self.read.notify(usize::MAX);
+			if let Some(reply) = reply {
+				return reply.map(drop).map_err(Error::from);
+			}
+			self.socket.readable().await.map_err(io_error)?;
+		}
+	}
+}
+
+/// Does not own the descriptor, which is closed along with the xcb connection.
+#[cfg(feature = "async")]
+struct Socket(RawFd);
+
+#[cfg(feature = "async")]
+impl AsFd for Socket {
+	fn as_fd(&self) -> BorrowedFd<'_> {
+		// SAFETY: the descriptor stays open as long as the connection, which outlives the socket.
+		unsafe { BorrowedFd::borrow_raw(self.0) }
+	}
+}
+
+#[cfg(feature = "async")]
+fn io_error(error: std::io::Error) -> Error {
+	Error::ConnectionFailed(error.to_string())
+}
+
+/// [`PropertyEvents`] driven by readiness of the socket.
+#[cfg(feature = "async")]
+pub(crate) struct AsyncPropertyEvents<'a> {
+	connection: &'a AsyncConnection,
+	snapshot: Snapshot,
+	pending: VecDeque<WindowEvent>,
+}
+
+#[cfg(feature = "async")]
+impl AsyncPropertyEvents<'_> {
+	pub(crate) async fn next(&mut self) -> Result<WindowEvent> {
+		loop {
+			if let Some(event) = self.pending.pop_front() {
+				return Ok(event);
+			}
+			// Listening before polling catches whatever a query reads from the socket in between.
+			let read = self.connection.read.listen();
+			let event = {
+				let _reader = self.connection.reader.lock().await;
+				self.connection.connection.connection.poll_for_event()
+			};
+			match event {
+				Ok(Some(xcb::Event::X(x::Event::PropertyNotify(event)))) => self.property_changed(&event).await?,
+				Ok(Some(_)) | Err(xcb::Error::Protocol(_)) => {},
+				Ok(None) => future::or(async { self.connection.socket.readable().await.map_err(io_error) }, async {
+					read.await;
+					Ok(())
+				}).await?,
+				Err(error) => return Err(error.into()),
+			}
+		}
+	}
+
+	async fn property_changed(&mut self, event: &x::PropertyNotifyEvent) -> Result<()> {
+		let connection = &self.connection.connection;
+		if event.atom() == connection.client_list {
+			let events = self.snapshot.windows(self.connection.windows().await?);
+			for event in &events {
+				if let WindowEvent::Opened(window) = event {
+					connection.select_property_changes(window_from_id(window.id));
+				}
+			}
+			connection.connection.flush()?;
+			self.pending.extend(events);
+		} else if event.atom() == connection.active_window {
+			self.pending.extend(self.snapshot.active(self.connection.active_window().await?));
+		} else if event.atom() == connection.window_name || event.atom() == x::ATOM_WM_NAME {
+			if let Ok(window) = self.connection.window_info(event.window()).await {
+				self.pending.extend(self.snapshot.title(window));
+			}
+		}
+		Ok(())
+	}
+}
+
+/// The value of a property, failing instead of panicking when a client stored it in an unexpected format.
+fn values<P: x::PropEl>(reply: &x::GetPropertyReply) -> Result<&[P]> {
+	match reply.format() {
+		0 => Ok(&[]),
+		format if format == P::FORMAT => Ok(reply.value()),
+		format => Err(Error::Decode(format!("property of format {} where {} was expected", format, P::FORMAT))),
+	}
+}
+
+/// Where the chunk after the one read from `offset` starts, `None` once the property is read or
+/// the maximum length reached. A property stored with another type than the requested one comes
+/// back without a value and its whole length in `bytes_after`, further chunks would be just as empty.
+fn next_chunk(requested: Atom, stored: Atom, received: usize, bytes_after: u32, offset: u32, max_length: u32) -> Option<u32> {
+	let next = offset + CHUNK_LENGTH.min(max_length - offset);
+	let matches = stored == requested || requested == x::ATOM_ANY;
+	(bytes_after != 0 && received != 0 && matches && next < max_length).then_some(next)
+}
+
+/// Rejects a screen index past the `count` screens of the display, which would leave no root to enumerate.
+fn valid_screen(screen: Option<usize>, count: usize) -> Result<Option<usize>> {
+	match screen {
+		Some(screen) if screen >= count => Err(Error::NoSuchScreen(screen)),
+		screen => Ok(screen),
+	}
+}
+
+/// The outer bounds of a client at `x`, `y` whose window manager frame extends it by the
+/// `_NET_FRAME_EXTENTS` left, right, top and bottom. Extents wider than any X coordinate
+/// could span are bogus and ignored.
+fn framed(x: i16, y: i16, width: u16, height: u16, extents: &[u32]) -> Geometry {
+	let (left, right, top, bottom) = match *extents {
+		[left, right, top, bottom] if extents.iter().all(|&extent| extent <= u32::from(u16::MAX)) => (left, right, top, bottom),
+		_ => (0, 0, 0, 0),
+	};
+	Geometry {
+		x: i32::from(x) - left as i32,
+		y: i32::from(y) - top as i32,
+		width: u32::from(width) + left + right,
+		height: u32::from(height) + top + bottom,
+	}
+}
+
+/// Turns the clients of every root, listed from the bottom up, into the topmost first while
+/// keeping the screens in order.
+fn stacked(mut windows: Vec<WindowInfo>) -> Vec<WindowInfo> {
+	let mut start = 0;
+	while start < windows.len() {
+		let screen = windows[start].screen;
+		let end = windows[start..].iter().position(|window| window.screen != screen).map_or(windows.len(), |length| start + length);
+		windows[start..end].reverse();
+		start = end;
+	}
+	windows
+}
+
+/// Pairs the desktop count with the null separated `_NET_DESKTOP_NAMES`, which may name fewer
+/// or more desktops than there are.
+fn desktops(count: u32, current: Option<u32>, names: &[u8]) -> Vec<Desktop> {
+	let mut names = names.split(|&byte| byte == 0).map(|name| String::from_utf8_lossy(name).into_owned());
+	(0..count)
+		.map(|index| Desktop { index, name: names.next().filter(|name| !name.is_empty()), current: current == Some(index) })
+		.collect()
+}
+
+/// The first of the `_NET_WM_WINDOW_TYPE` values, listed in order of preference, that is `known`.
+/// Windows without one are dialogs if they are transient for another window, normal otherwise.
+fn window_type(known: &[(Atom, WindowType)], types: &[Atom], transient: bool) -> WindowType {
+	match types.iter().find_map(|atom| known.iter().find(|(known, _)| known == atom)) {
+		Some(&(_, window_type)) => window_type,
+		None if transient => WindowType::Dialog,
+		None => WindowType::Normal,
+	}
+}
+
+/// Prefers `_NET_WM_NAME` of type `utf8_string`, falling back to the legacy `WM_NAME` in whichever
+/// encoding it was set. A `WM_NAME` of a type without a decoder, like `C_STRING`, is read as UTF-8.
+fn title(utf8_string: Atom, compound_text: Atom, (r#type, name): (Atom, Vec<u8>), (legacy_type, value): (Atom, Vec<u8>)) -> (String, TitleSource) {
+	let name = match String::from_utf8(name) {
+		Ok(title) if r#type == utf8_string => return (title, TitleSource::NetWmName),
+		Ok(title) => title.into_bytes(),
+		Err(error) => error.into_bytes(),
+	};
+	let title = match legacy_type {
+		x::ATOM_NONE if r#type == utf8_string => return (String::from_utf8_lossy(&name).into_owned(), TitleSource::NetWmName),
+		x::ATOM_NONE => return (String::new(), TitleSource::Untitled),
+		x::ATOM_STRING => encoding::latin1(&value),
+		r#type if r#type == compound_text => encoding::compound_text(&value),
+		_ => String::from_utf8_lossy(&value).into_owned(),
+	};
+	(title, TitleSource::WmName)
+}
+
+/// Splits `WM_CLASS` into its two null terminated strings, the instance followed by the class.
+fn wm_class(value: &[u8]) -> (Option<String>, Option<String>) {
+	let mut strings = value.split(|&byte| byte == 0)
+		.map(|string| Some(String::from_utf8_lossy(string).into_owned()).filter(|string| !string.is_empty()));
+	(strings.next().flatten(), strings.next().flatten())
+}
+
+fn window_from_id(id: u64) -> Window {
+	Window::new(id as u32)
+}
+
+fn intern_atom(connection: &XConnection, name: &str) -> Result<Atom> {
+	let cookie = connection.send_request(&x::InternAtom { only_if_exists: false, name: name.as_bytes() });
+	Ok(connection.wait_for_reply(cookie)?.atom())
+}
+
+/// Interns several atoms in a single round trip.
+fn intern_atoms<'a>(connection: &XConnection, names: impl Iterator<Item = &'a str>) -> Result<Vec<Atom>> {
+	let cookies: Vec<_> = names.map(|name| connection.send_request(&x::InternAtom { only_if_exists: false, name: name.as_bytes() })).collect();
+	cookies.into_iter().map(|cookie| Ok(connection.wait_for_reply(cookie)?.atom())).collect()
+}
+
+impl From<xcb::ConnError> for Error {
+	fn from(error: xcb::ConnError) -> Self {
+		Error::ConnectionFailed(error.to_string())
+	}
+}
+
+impl From<xcb::Error> for Error {
+	fn from(error: xcb::Error) -> Self {
+		match error {
+			xcb::Error::Connection(error) => error.into(),
+			xcb::Error::Protocol(error) => Error::Protocol(error.to_string()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{collections::HashMap, convert::TryInto, io::{Read, Write}, os::{fd::OwnedFd, unix::net::UnixStream}, thread};
+
+	use super::*;
+
+	const ROOT: u32 = 0x100;
+	const FIRST: u32 = 0x20_0001;
+	const SECOND: u32 = 0x20_0002;
+
+	/// A property the test server holds, atoms given by name.
+	enum Value {
+		Text(&'static str, String),
+		Windows(Vec<u32>),
+		Atoms(Vec<&'static str>),
+	}
+
+	/// Runs an X server on one end of a socket pair, with a single screen whose root lists
+	/// the clients [`FIRST`] and [`SECOND`] and the given properties, until the client hangs up.
+	fn server(properties: Vec<(u32, &'static str, Value)>) -> XConnection {
+		let (mut server, client) = UnixStream::pair().unwrap();
+		thread::spawn(move || {
+			let mut atoms: Vec<(String, u32)> = [("ATOM", 4), ("CARDINAL", 6), ("STRING", 31), ("WINDOW", 33), ("WM_HINTS", 35), ("WM_NAME", 39), ("WM_CLASS", 67), ("WM_TRANSIENT_FOR", 68)]
+				.iter().map(|&(name, atom)| (name.to_string(), atom)).collect();
+			let mut intern = |name: &str| match atoms.iter().find(|(known, _)| known == name) {
+				Some(&(_, atom)) => atom,
+				None => {
+					let atom = 69 + atoms.len() as u32;
+					atoms.push((name.to_string(), atom));
+					atom
+				},
+			};
+			let mut values = HashMap::new();
+			for (window, property, value) in properties {
+				let value = match value {
+					Value::Text(r#type, text) => (intern(r#type), 8, text.into_bytes()),
+					Value::Windows(windows) => (intern("WINDOW"), 32, windows.iter().flat_map(|window| window.to_le_bytes()).collect()),
+					Value::Atoms(names) => (intern("ATOM"), 32, names.iter().flat_map(|name| intern(name).to_le_bytes()).collect()),
+				};
+				values.insert((window, intern(property)), value);
+			}
+			let mut setup = [0; 12];
+			server.read_exact(&mut setup).unwrap();
+			server.write_all(&setup_reply()).unwrap();
+			let mut sequence = 0u16;
+			let mut header = [0; 4];
+			while server.read_exact(&mut header).is_ok() {
+				let mut body = vec![0; usize::from(u16::from_le_bytes([header[2], header[3]])) * 4 - 4];
+				server.read_exact(&mut body).unwrap();
+				sequence = sequence.wrapping_add(1);
+				let word = |offset: usize| u32::from_le_bytes(body[offset..offset + 4].try_into().unwrap());
+				let known = |window: u32| [ROOT, FIRST, SECOND].contains(&window);
+				let response = match header[0] {
+					// ChangeWindowAttributes has no reply.
+					2 => continue,
+					3 if known(word(0)) => {
+						let mut attributes = [0; 36];
+						attributes[18] = 2;
+						reply(sequence, 0, &attributes)
+					},
+					3 => error(sequence, 3, header[0]),
+					14 if known(word(0)) => reply(sequence, 24, &[&ROOT.to_le_bytes()[..], &[10, 0, 20, 0, 100, 0, 50, 0]].concat()),
+					14 => error(sequence, 9, header[0]),
+					15 => {
+						let children: &[u32] = if word(0) == ROOT { &[FIRST, SECOND] } else { &[] };
+						let mut tree = [ROOT.to_le_bytes(), ROOT.to_le_bytes()].concat();
+						tree.extend((children.len() as u16).to_le_bytes());
+						tree.resize(24, 0);
+						tree.extend(children.iter().flat_map(|child| child.to_le_bytes()));
+						reply(sequence, 0, &tree)
+					},
+					16 => {
+						let length = usize::from(u16::from_le_bytes([body[0], body[1]]));
+						reply(sequence, 0, &intern(std::str::from_utf8(&body[4..4 + length]).unwrap()).to_le_bytes())
+					},
+					20 => match values.get(&(word(0), word(4))) {
+						None => reply(sequence, 0, &[0; 12]),
+						Some((r#type, format, _)) if word(8) != 0 && word(8) != *r#type => {
+							reply(sequence, *format, &[r#type.to_le_bytes(), 0u32.to_le_bytes(), 0u32.to_le_bytes()].concat())
+						},
+						Some((r#type, format, value)) => {
+							let start = (word(12) as usize * 4).min(value.len());
+							let end = (start + word(16) as usize * 4).min(value.len());
+							let mut property = [r#type.to_le_bytes(), ((value.len() - end) as u32).to_le_bytes(), ((end - start) as u32 / u32::from(*format / 8)).to_le_bytes()].concat();
+							property.resize(24, 0);
+							property.extend_from_slice(&value[start..end]);
+							reply(sequence, *format, &property)
+						},
+					},
+					40 => reply(sequence, 1, &[0, 0, 0, 0, 10, 0, 20, 0]),
+					43 => reply(sequence, 0, &SECOND.to_le_bytes()),
+					// Queried extensions are absent.
+					98 => reply(sequence, 0, &[0]),
+					_ => error(sequence, 1, header[0]),
+				};
+				if server.write_all(&response).is_err() {
+					break;
+				}
+			}
+		});
+		XConnection::connect_with_fd(OwnedFd::from(client), None).unwrap()
+	}
+
+	/// The connection setup announcing a single 1920x1080 screen with [`ROOT`] as its root.
+	fn setup_reply() -> Vec<u8> {
+		let mut setup = vec![0; 16];
+		setup[4..8].copy_from_slice(&0x20_0000u32.to_le_bytes());
+		setup[8..12].copy_from_slice(&0x1f_ffffu32.to_le_bytes());
+		setup.extend([4, 0, 0xff, 0xff, 1, 0, 0, 0, 32, 32, 8, 255, 0, 0, 0, 0]);
+		setup.extend(b"test");
+		for word in [ROOT, 0x20, 0xff_ffff, 0, 0] {
+			setup.extend(word.to_le_bytes());
+		}
+		for half in [1920u16, 1080, 500, 300, 1, 1] {
+			setup.extend(half.to_le_bytes());
+		}
+		setup.extend(0x21u32.to_le_bytes());
+		setup.extend([0, 0, 24, 0]);
+		let mut reply = vec![1, 0, 11, 0, 0, 0];
+		reply.extend((setup.len() as u16 / 4).to_le_bytes());
+		reply.extend(setup);
+		reply
+	}
+
+	/// A reply whose fields after the sequence number and length are `body`.
+	fn reply(sequence: u16, data: u8, body: &[u8]) -> Vec<u8> {
+		let length = body.len().max(24).div_ceil(4) * 4;
+		let mut reply = vec![1, data];
+		reply.extend(sequence.to_le_bytes());
+		reply.extend(((length - 24) as u32 / 4).to_le_bytes());
+		reply.extend(body);
+		reply.resize(8 + length, 0);
+		reply
+	}
+
+	fn error(sequence: u16, code: u8, major: u8) -> Vec<u8> {
+		let mut error = vec![0, code];
+		error.extend(sequence.to_le_bytes());
+		error.resize(32, 0);
+		error[10] = major;
+		error
+	}
+
+	/// An EWMH window manager listing two clients, the focused second one with a title longer
+	/// than a chunk.
+	fn scripted() -> Vec<(u32, &'static str, Value)> {
+		vec![
+			(ROOT, "_NET_SUPPORTED", Value::Atoms(vec!["_NET_CLIENT_LIST", "_NET_ACTIVE_WINDOW"])),
+			(ROOT, "_NET_CLIENT_LIST", Value::Windows(vec![FIRST, SECOND])),
+			(ROOT, "_NET_ACTIVE_WINDOW", Value::Windows(vec![SECOND])),
+			(FIRST, "_NET_WM_NAME", Value::Text("UTF8_STRING", "a".into())),
+			(FIRST, "WM_CLASS", Value::Text("STRING", "xterm\0XTerm\0".into())),
+			(SECOND, "WM_NAME", Value::Text("STRING", "b".repeat(20000))),
+		]
+	}
+
+	fn summary(windows: &[WindowInfo]) -> Vec<(u64, usize, Option<&str>)> {
+		windows.iter().map(|window| (window.id, window.title.len(), window.class.as_deref())).collect()
+	}
+
+	#[test]
+	fn test_server() {
+		let connection = Connection::from_connection(server(scripted())).unwrap();
+		assert_eq!(connection.discovery(), Discovery::ClientList);
+		let windows = connection.windows().unwrap();
+		assert_eq!(summary(&windows), [(u64::from(FIRST), 1, Some("XTerm")), (u64::from(SECOND), 20000, None)]);
+		assert_eq!(windows[0].geometry, Some(Geometry { x: 10, y: 20, width: 100, height: 50 }));
+		assert_eq!(connection.active_window().unwrap().map(|window| window.id), Some(u64::from(SECOND)));
+	}
+
+	/// Queries from several threads at once, each waiting for replies another one may read.
+	#[cfg(feature = "async")]
+	#[test]
+	fn test_async_concurrently() {
+		use futures_lite::future::{block_on, or, zip};
+
+		let connection = AsyncConnection::new(Connection::from_connection(server(scripted())).unwrap()).unwrap();
+		thread::scope(|scope| {
+			for _ in 0..4 {
+				scope.spawn(|| block_on(or(async {
+					for _ in 0..25 {
+						let (windows, active) = zip(connection.windows(), connection.active_window()).await;
+						assert_eq!(summary(&windows.unwrap()), [(u64::from(FIRST), 1, Some("XTerm")), (u64::from(SECOND), 20000, None)]);
+						assert_eq!(active.unwrap().map(|window| window.title.len()), Some(20000));
+					}
+				}, async {
+					async_io::Timer::after(std::time::Duration::from_secs(20)).await;
+					panic!("a query never completed");
+				})));
+			}
+		});
+	}
+
+	#[test]
+	fn test_stacked() {
+		let window = |id, screen| WindowInfo { id, screen: Some(screen), ..WindowInfo::default() };
+		let windows = stacked(vec![window(1, 0), window(2, 0), window(3, 0), window(4, 1), window(5, 1)]);
+		assert_eq!(windows.iter().map(|window| window.id).collect::<Vec<_>>(), [3, 2, 1, 5, 4]);
+		assert_eq!(stacked(Vec::new()), Vec::new());
+	}
+
+	#[test]
+	fn test_next_chunk() {
+		let utf8 = Atom::new(300);
+		let max = 3 * CHUNK_LENGTH;
+		assert_eq!(next_chunk(utf8, utf8, 4, 10, 0, max), Some(CHUNK_LENGTH));
+		assert_eq!(next_chunk(utf8, utf8, 4, 10, CHUNK_LENGTH, max), Some(2 * CHUNK_LENGTH));
+		assert_eq!(next_chunk(utf8, utf8, 4, 10, 2 * CHUNK_LENGTH, max), None);
+		assert_eq!(next_chunk(utf8, utf8, 4, 0, 0, max), None);
+		assert_eq!(next_chunk(utf8, utf8, 4, 10, 0, 256), None);
+		assert_eq!(next_chunk(utf8, x::ATOM_STRING, 0, 4096, 0, max), None);
+		assert_eq!(next_chunk(x::ATOM_ANY, x::ATOM_STRING, 4, 10, 0, max), Some(CHUNK_LENGTH));
+	}
+
+	#[test]
+	fn test_valid_screen() {
+		assert_eq!(valid_screen(Some(1), 2), Ok(Some(1)));
+		assert_eq!(valid_screen(None, 2), Ok(None));
+		assert_eq!(valid_screen(Some(2), 2), Err(Error::NoSuchScreen(2)));
+		assert_eq!(valid_screen(Some(0), 0), Err(Error::NoSuchScreen(0)));
+	}
+
+	#[test]
+	fn test_framed() {
+		assert_eq!(framed(10, 30, 800, 600, &[2, 2, 24, 2]), Geometry { x: 8, y: 6, width: 804, height: 626 });
+		assert_eq!(framed(-5, 0, 100, 100, &[]), Geometry { x: -5, y: 0, width: 100, height: 100 });
+		assert_eq!(framed(0, 0, 100, 100, &[0x8000_0000, 0, 0, 0]), Geometry { x: 0, y: 0, width: 100, height: 100 });
+		assert_eq!(framed(i16::MIN, i16::MIN, u16::MAX, u16::MAX, &[65535; 4]), Geometry { x: -98303, y: -98303, width: 196605, height: 196605 });
+	}
+
+	#[test]
+	fn test_desktops() {
+		let desktop = |index, name: Option<&str>, current| Desktop { index, name: name.map(String::from), current };
+		assert_eq!(desktops(3, Some(1), b"Mail\0Code\0"), vec![
+			desktop(0, Some("Mail"), false),
+			desktop(1, Some("Code"), true),
+			desktop(2, None, false),
+		]);
+		assert_eq!(desktops(1, None, b"Web\0Chat\0"), vec![desktop(0, Some("Web"), false)]);
+		assert_eq!(desktops(0, Some(0), b""), Vec::new());
+	}
+
+	#[test]
+	fn test_window_state() {
+		let atom = |id| Atom::new(id);
+		let states = StateAtoms {
+			state: atom(1), hidden: atom(2), maximized_vert: atom(3), maximized_horz: atom(4), fullscreen: atom(5),
+			demands_attention: atom(6), skip_taskbar: atom(7),
+		};
+		assert_eq!(states.window_state(&[], &[], &[]), WindowState::empty());
+		assert_eq!(states.window_state(&[atom(3), atom(4), atom(9)], &[1], &[0]), WindowState::MAXIMIZED);
+		assert_eq!(states.window_state(&[atom(3), atom(5)], &[1], &[URGENCY_HINT]), WindowState::FULLSCREEN | WindowState::URGENT);
+		assert_eq!(states.window_state(&[atom(2)], &[ICONIC_STATE, 0], &[]), WindowState::MINIMIZED | WindowState::HIDDEN);
+		assert_eq!(states.window_state(&[atom(6), atom(7)], &[], &[]), WindowState::URGENT | WindowState::SKIP_TASKBAR);
+	}
+
+	#[test]
+	fn test_window_type() {
+		let known = [(Atom::new(1), WindowType::Dock), (Atom::new(2), WindowType::Utility)];
+		assert_eq!(window_type(&known, &[Atom::new(9), Atom::new(2), Atom::new(1)], false), WindowType::Utility);
+		assert_eq!(window_type(&known, &[Atom::new(9)], true), WindowType::Dialog);
+		assert_eq!(window_type(&known, &[], false), WindowType::Normal);
+	}
+
+	#[test]
+	fn test_title() {
+		let (utf8, compound_text, c_string) = (Atom::new(300), Atom::new(301), Atom::new(302));
+		let titled = |(r#type, name): (Atom, &[u8]), (legacy_type, value): (Atom, &[u8])| {
+			title(utf8, compound_text, (r#type, name.to_vec()), (legacy_type, value.to_vec()))
+		};
+		let unset = (x::ATOM_NONE, &b""[..]);
+		assert_eq!(titled((utf8, "Grüße".as_bytes()), (x::ATOM_STRING, b"Gr\xfc\xdfe")), ("Grüße".into(), TitleSource::NetWmName));
+		assert_eq!(titled((utf8, b"caf\xe9"), unset), ("caf\u{fffd}".into(), TitleSource::NetWmName));
+		assert_eq!(titled((utf8, b"caf\xe9"), (x::ATOM_STRING, b"caf\xe9")), ("café".into(), TitleSource::WmName));
+		assert_eq!(titled(unset, (utf8, "café".as_bytes())), ("café".into(), TitleSource::WmName));
+		assert_eq!(titled(unset, (compound_text, b"xterm")), ("xterm".into(), TitleSource::WmName));
+		assert_eq!(titled(unset, (c_string, b"xclock")), ("xclock".into(), TitleSource::WmName));
+		assert_eq!(titled(unset, unset), (String::new(), TitleSource::Untitled));
+	}
+
+	#[test]
+	fn test_wm_class() {
+		assert_eq!(wm_class(b"navigator\0Firefox\0"), (Some("navigator".into()), Some("Firefox".into())));
+		assert_eq!(wm_class(b"\0XTerm\0"), (None, Some("XTerm".into())));
+		assert_eq!(wm_class(b"xterm"), (Some("xterm".into()), None));
+		assert_eq!(wm_class(b""), (None, None));
 	}
 }